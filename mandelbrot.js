@@ -26,14 +26,22 @@ async function run() {
     app.update();
     drawImage();
 
-    async function zoom(event, out) {
+    const canvasPosition = (event) => {
         const boundingRect = canvas.getBoundingClientRect();
         const scaleX = canvas.width / boundingRect.width;
         const scaleY = canvas.height / boundingRect.height;
-        const canvasLeft = (event.clientX - boundingRect.left) * scaleX;
-        const canvasBottom = (event.clientY - boundingRect.top) * scaleY;
+        return [(event.clientX - boundingRect.left) * scaleX,
+                (event.clientY - boundingRect.top) * scaleY];
+    }
+
+    async function zoom(event, out) {
+        const [canvasLeft, canvasBottom] = canvasPosition(event);
         const point = Point.new(canvasLeft, canvasBottom);
-        app.zoom(point, out);
+        try {
+            app.zoom(point, out);
+        } catch (error) {
+            console.warn(error.message);
+        }
     }
 
     canvas.addEventListener("click", event => {
@@ -48,8 +56,21 @@ async function run() {
         return false;
     });
 
+    const coordinates = document.getElementById("coordinates");
+    canvas.addEventListener("mousemove", event => {
+        const [x, y] = canvasPosition(event);
+        const z = app.pixel_to_complex(x, y);
+        const sign = z.im < 0 ? "-" : "+";
+        coordinates.textContent = `${z.re.toPrecision(12)} ${sign} ${Math.abs(z.im).toPrecision(12)}i`;
+        z.free();
+    });
+
     async function shift(direction) {
-        app.shift(direction);
+        try {
+            app.shift(direction);
+        } catch (error) {
+            console.warn(error.message);
+        }
         drawImage();
     }
 
@@ -62,12 +83,33 @@ async function run() {
     left.onclick = () => { shift(Key.Left) };
     right.onclick = () => { shift(Key.Right) };
 
+    const back = document.getElementById("back");
+    back.onclick = () => { if (app.back()) { drawImage(); } };
+    const forward = document.getElementById("forward");
+    forward.onclick = () => { if (app.forward()) { drawImage(); } };
+
     const reset = document.getElementById("reset");
     reset.onclick = () => { app.reset(); drawImage(); };
 
-    save = document.getElementById("save");
+    canvas.addEventListener("dragover", event => event.preventDefault());
+    canvas.addEventListener("drop", async event => {
+        event.preventDefault();
+        const file = event.dataTransfer.files[0];
+        if (!file) {
+            return;
+        }
+        try {
+            app.load_from_png(new Uint8Array(await file.arrayBuffer()));
+            drawImage();
+        } catch (error) {
+            console.warn(error.message);
+        }
+    });
+
+    const save = document.getElementById("save");
     save.addEventListener('click', function (e) {
-        save.href = canvas.toDataURL('image/png');
+        URL.revokeObjectURL(save.href);
+        save.href = URL.createObjectURL(new Blob([app.png()], { type: 'image/png' }));
     });
 
 }
@@ -0,0 +1,118 @@
+use num::Complex;
+use wasm_bindgen::prelude::*;
+
+use crate::rng::Rng;
+use crate::{in_mandelbrot_set, iterate, ApplicationSettings, Error, PlotRange};
+
+// Area of the Mandelbrot set within a rectangle. Sample points are proven members
+// by the cardioid/bulb test (interior), undecided after max_iterations (bounded)
+// or proven non-members (escaped). Bounded points count as members, so `bounded`
+// tells how much of the estimate rests on the iteration limit.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AreaEstimate {
+    pub area: f64,
+    pub error: f64,
+    pub samples: u32,
+    pub interior: u32,
+    pub bounded: u32,
+    pub escaped: u32,
+}
+
+impl AreaEstimate {
+    fn new(samples: u32) -> AreaEstimate {
+        AreaEstimate { area: 0.0, error: 0.0, samples, interior: 0, bounded: 0, escaped: 0 }
+    }
+    // Classifies c and returns whether it counts as a member
+    fn count(&mut self, c: &Complex<f64>, settings: &ApplicationSettings) -> bool {
+        if in_mandelbrot_set(c) {
+            self.interior += 1;
+            true
+        } else if iterate(c, settings, |_| {}).is_some() {
+            self.escaped += 1;
+            false
+        } else {
+            self.bounded += 1;
+            true
+        }
+    }
+    fn fraction(&self) -> f64 {
+        (self.interior + self.bounded) as f64 / self.samples as f64
+    }
+}
+
+// Widest pixel counting grid, as two rows of memberships are held in memory
+const MAX_COLUMNS: u32 = 1 << 16;
+
+fn validate(range: &PlotRange) -> Result<f64, Error> {
+    let area = range.width() * -range.height();
+    if !(area.is_finite() && area > 0.0) {
+        return Err(Error::DegenerateRange);
+    }
+    Ok(area)
+}
+
+// Pixel counting: one sample at the center of each cell of a columns x rows grid.
+// The error bound is the total area of the cells whose membership differs from one
+// of their four neighbours, as only those can straddle the boundary of the set.
+pub(crate) fn pixel_count(range: &PlotRange, columns: u32, rows: u32,
+                          settings: &ApplicationSettings) -> Result<AreaEstimate, Error> {
+    let total = validate(range)?;
+    if columns > MAX_COLUMNS {
+        return Err(Error::InvalidArgument("grid must have at most 65536 columns"));
+    }
+    let samples = columns.checked_mul(rows).filter(|&n| n > 0)
+        .ok_or(Error::InvalidArgument("grid must have between 1 and 2^32 - 1 cells"))?;
+    let (width, height) = (columns as usize, rows as usize);
+    let mut estimate = AreaEstimate::new(samples);
+    // Only two rows of memberships are kept, so that fine grids fit into memory
+    let mut previous: Vec<(bool, bool)> = Vec::new();
+    let mut boundary = 0;
+    for y in 0..height {
+        let mut current: Vec<(bool, bool)> = (0..width)
+            .map(|x| {
+                let c = range.pixel_to_point(x as f64 + 0.5, y as f64 + 0.5, width, height);
+                (estimate.count(&c, settings), false)
+            })
+            .collect();
+        for x in 0..width {
+            if x + 1 < width && current[x].0 != current[x + 1].0 {
+                current[x].1 = true;
+                current[x + 1].1 = true;
+            }
+            if let Some(above) = previous.get_mut(x) {
+                if above.0 != current[x].0 {
+                    above.1 = true;
+                    current[x].1 = true;
+                }
+            }
+        }
+        boundary += previous.iter().filter(|cell| cell.1).count();
+        previous = current;
+    }
+    boundary += previous.iter().filter(|cell| cell.1).count();
+    estimate.area = total * estimate.fraction();
+    estimate.error = total * boundary as f64 / samples as f64;
+    Ok(estimate)
+}
+
+// Monte Carlo: uniformly distributed samples; the error is one standard error of
+// the binomial proportion of members
+pub(crate) fn monte_carlo(range: &PlotRange, samples: u32, seed: u64,
+                          settings: &ApplicationSettings) -> Result<AreaEstimate, Error> {
+    let total = validate(range)?;
+    if samples == 0 {
+        return Err(Error::InvalidArgument("at least one sample is required"));
+    }
+    let mut rng = Rng::new(seed);
+    let mut estimate = AreaEstimate::new(samples);
+    for _ in 0..samples {
+        let c = Complex::new(rng.uniform(range.top_left.re, range.bottom_right.re),
+                             rng.uniform(range.bottom_right.im, range.top_left.im));
+        estimate.count(&c, settings);
+    }
+    let p = estimate.fraction();
+    estimate.area = total * p;
+    estimate.error = total * (p * (1.0 - p) / samples as f64).sqrt();
+    Ok(estimate)
+}
@@ -0,0 +1,41 @@
+use num::Complex;
+
+use crate::render::{Dwell, Frame};
+use crate::{colour, iterate, lines, smooth_iteration, ApplicationSettings, PlotRange, DEGREE};
+
+// Hue advance per period; the golden ratio keeps neighbouring periods apart
+const HUE_STEP: f64 = 0.618_033_988_749_895;
+// Brightness of escaping pixels, which sets the Mandelbrot set itself apart
+const ESCAPED_VALUE: f64 = 0.6;
+
+// Iteration n >= 1 at which |z_n| is smallest, i.e. the period of the atom domain
+// containing c, and the continuous iteration count and final iterate if the orbit escaped
+pub(crate) fn domain(c: &Complex<f64>, settings: &ApplicationSettings) -> (u32, Option<(f64, Complex<f64>)>) {
+    let (mut n, mut period, mut minimum) = (0, 0, f64::INFINITY);
+    let escaped = iterate(c, settings, |z| {
+        n += 1;
+        if z.norm_sqr() < minimum {
+            minimum = z.norm_sqr();
+            period = n;
+        }
+    }).map(|(i, modulus, z)| (smooth_iteration(i, modulus, settings.bailout, DEGREE), z));
+    (period, escaped)
+}
+
+// Colours every pixel by the period of its atom domain. Returns the periods in
+// row-major order together with the frame of dwells, all of which are iterated.
+pub(crate) fn render(range: &PlotRange, settings: &ApplicationSettings, width: usize, height: usize,
+                     buffer: &mut [u32]) -> (Vec<u32>, Frame) {
+    let mut periods = Vec::with_capacity(width * height);
+    let mut dwells = Vec::with_capacity(width * height);
+    for (index, value) in buffer.iter_mut().enumerate().take(width * height) {
+        let c = range.pixel_to_point((index % width) as f64, (index / width) as f64, width, height);
+        let (period, escaped) = domain(&c, settings);
+        let brightness = if escaped.is_some() { ESCAPED_VALUE } else { 1.0 };
+        *value = colour::hsv(period as f64 * HUE_STEP, 0.7, brightness);
+        periods.push(period);
+        dwells.push(escaped.map_or(Dwell::Bounded,
+                                   |(tau, z)| Dwell::Escaped(tau as u32, *value, lines::cell(settings.lines, tau, &z))));
+    }
+    (periods, Frame { dwells, iterated: width * height })
+}
@@ -0,0 +1,76 @@
+// Exports the raw iteration data of a view, e.g. for numpy:
+//
+//     mandelbrot-iterations [--view SAVED.png] [--size WIDTHxHEIGHT]
+//                           [--format npy-f32|npy-f64|raw-f32|raw-f64] OUTPUT
+//
+// Without --view the start view is used; saved images carry their view in their
+// text chunks. See src/iterations.rs for the formats.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::process;
+
+use mandelbrot_wasm::{Application, DataFormat};
+
+const USAGE: &str = "usage: mandelbrot-iterations [--view SAVED.png] [--size WIDTHxHEIGHT] \
+                     [--format npy-f32|npy-f64|raw-f32|raw-f64] OUTPUT";
+
+struct Options {
+    view: Option<String>,
+    width: u32,
+    height: u32,
+    format: DataFormat,
+    output: String,
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let (mut view, mut size, mut format, mut output) = (None, (800, 600), DataFormat::NpyF32, None);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--view" => view = Some(value()?),
+            "--size" => {
+                let value = value()?;
+                let mut parts = value.split('x').map(|part| part.parse::<u32>());
+                size = match (parts.next(), parts.next(), parts.next()) {
+                    (Some(Ok(width)), Some(Ok(height)), None) => (width, height),
+                    _ => return Err(format!("invalid size {}", value))
+                };
+            }
+            "--format" => {
+                format = match value()?.as_str() {
+                    "npy-f32" => DataFormat::NpyF32,
+                    "npy-f64" => DataFormat::NpyF64,
+                    "raw-f32" => DataFormat::RawF32,
+                    "raw-f64" => DataFormat::RawF64,
+                    other => return Err(format!("unknown format {}", other))
+                };
+            }
+            _ if output.is_none() && !arg.starts_with("--") => output = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg))
+        }
+    }
+    let output = output.ok_or("missing output file")?;
+    Ok(Options { view, width: size.0, height: size.1, format, output })
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let mut app = Application::new();
+    if let Some(view) = &options.view {
+        let bytes = fs::read(view).map_err(|error| format!("{}: {}", view, error))?;
+        app.restore_png(&bytes).map_err(|error| format!("{}: {}", view, error))?;
+    }
+    let file = File::create(&options.output).map_err(|error| format!("{}: {}", options.output, error))?;
+    app.write_iterations(BufWriter::new(file), options.width, options.height, options.format)
+        .map_err(|error| error.to_string())?
+        .flush()
+        .map_err(|error| format!("{}: {}", options.output, error))
+}
+
+fn main() {
+    let result = parse(std::env::args().skip(1)).and_then(run);
+    if let Err(message) = result {
+        eprintln!("{}\n{}", message, USAGE);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,125 @@
+use num::Complex;
+
+use crate::rng::Rng;
+use crate::{in_mandelbrot_set, iterate, ApplicationSettings, Fractal, PlotRange};
+
+// Samples are drawn from the square of this half-width around the origin, which contains the set
+const SAMPLE_RADIUS: f64 = 2.0;
+// Nebulabrot iteration limits of the red, green and blue channels, relative to max_iterations
+const NEBULA_BANDS: [f64; 3] = [1.0, 0.1, 0.01];
+
+// Orbit density histograms (one per colour channel), accumulated over successive calls
+pub(crate) struct Buddhabrot {
+    histograms: [Vec<u32>; 3],
+    width: usize,
+    height: usize,
+    samples: u64,
+    rng: Rng,
+    orbit: Vec<Complex<f64>>,
+    // Mode, view and settings the histograms were accumulated for
+    key: Option<(Fractal, PlotRange, ApplicationSettings)>,
+}
+
+impl Buddhabrot {
+    pub fn new(width: usize, height: usize) -> Buddhabrot {
+        Buddhabrot { histograms: [vec![0; width * height], vec![0; width * height], vec![0; width * height]],
+                     width,
+                     height,
+                     samples: 0,
+                     rng: Rng::new(0),
+                     orbit: Vec::new(),
+                     key: None }
+    }
+    pub fn clear(&mut self) {
+        for histogram in self.histograms.iter_mut() {
+            histogram.iter_mut().for_each(|count| *count = 0);
+        }
+        self.samples = 0;
+        self.key = None;
+    }
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+    pub fn accumulate(&mut self, fractal: Fractal, range: &PlotRange, settings: &ApplicationSettings) {
+        let key = Some((fractal, *range, *settings));
+        if self.key != key {
+            self.clear();
+            self.rng = Rng::new(settings.seed as u64);
+            self.key = key;
+        }
+        for _ in 0..settings.samples {
+            let c = Complex { re: self.rng.uniform(-SAMPLE_RADIUS, SAMPLE_RADIUS),
+                              im: self.rng.uniform(-SAMPLE_RADIUS, SAMPLE_RADIUS) };
+            self.samples += 1;
+            let interior = in_mandelbrot_set(&c);
+            let orbit = &mut self.orbit;
+            orbit.clear();
+            if fractal == Fractal::AntiBuddhabrot {
+                if interior {
+                    // Known to be bounded, so skip the escape tests altogether
+                    let mut z = Complex { re: 0.0, im: 0.0 };
+                    for _ in 0..settings.max_iterations {
+                        z = z * z + c;
+                        orbit.push(z);
+                    }
+                } else if iterate(&c, settings, |z| orbit.push(*z)).is_some() {
+                    continue;
+                }
+                self.plot(0, self.orbit.len());
+                continue;
+            }
+            if interior {
+                continue;
+            }
+            let escape = match iterate(&c, settings, |z| orbit.push(*z)) {
+                Some((i, _, _)) => i,
+                None => continue
+            };
+            if fractal == Fractal::Nebulabrot {
+                for (channel, band) in NEBULA_BANDS.iter().enumerate() {
+                    if (escape as f64) < band * settings.max_iterations as f64 {
+                        self.plot(channel, self.orbit.len());
+                    }
+                }
+            } else {
+                self.plot(0, self.orbit.len());
+            }
+        }
+    }
+    fn plot(&mut self, channel: usize, length: usize) {
+        let range = match self.key {
+            Some((_, range, _)) => range,
+            None => return
+        };
+        for z in self.orbit[..length].iter() {
+            let (x, y) = range.point_to_pixel(z, self.width, self.height);
+            if x >= 0.0 && y >= 0.0 && x < self.width as f64 && y < self.height as f64 {
+                let count = &mut self.histograms[channel][y as usize * self.width + x as usize];
+                *count = count.saturating_add(1);
+            }
+        }
+    }
+    // Logarithmic tone mapping of the orbit density: grey levels for a single channel,
+    // independently normalised red, green and blue for the Nebulabrot
+    pub fn tone_map(&self, buffer: &mut [u32]) {
+        let channels = match self.key {
+            Some((Fractal::Nebulabrot, _, _)) => 3,
+            _ => 1
+        };
+        let levels: Vec<Vec<u32>> = self.histograms[..channels].iter().map(|histogram| {
+            let max = histogram.iter().copied().max().unwrap_or(0);
+            let scale = (1.0 + max as f64).ln();
+            histogram.iter()
+                .map(|&count| if max == 0 { 0 } else { (255.0 * (1.0 + count as f64).ln() / scale) as u32 })
+                .collect()
+        }).collect();
+        for (index, value) in buffer.iter_mut().enumerate() {
+            *value = if channels == 3 {
+                255 << 24 | levels[2][index] << 16 | levels[1][index] << 8 | levels[0][index]
+            } else {
+                let level = levels[0][index];
+                255 << 24 | level << 16 | level << 8 | level
+            };
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use num::Complex;
+use wasm_bindgen::prelude::*;
+
+use crate::{ApplicationSettings, PALETTE};
+
+// How escaped pixels are coloured: by their dwell, or by the argument of the final iterate
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Colouring {
+    Iterations,
+    // Palette shifted by half a cycle where the final iterate lies in the lower half plane
+    BinaryDecomposition,
+    // Same with `sectors` equal angular sectors
+    Decomposition,
+    // Argument of the final iterate as hue
+    AngleHue,
+    // Argument of the final iterate as palette index
+    AnglePalette,
+    // Period of the atom domain, i.e. the iteration at which |z| was smallest;
+    // Mandelbrot set only, other fractals fall back to Iterations
+    AtomDomain
+}
+
+// Argument of z in turns, within [0, 1)
+pub(crate) fn turn(z: &Complex<f64>) -> f64 {
+    (z.arg() / (2.0 * std::f64::consts::PI)).rem_euclid(1.0)
+}
+
+// Colour of a pixel escaping with dwell n and final iterate z
+pub(crate) fn shade(n: u32, z: &Complex<f64>, settings: &ApplicationSettings) -> u32 {
+    let size = PALETTE.len();
+    let decomposition = |sectors: u32| {
+        let sector = ((turn(z) * sectors as f64) as usize).min(sectors as usize - 1);
+        PALETTE[(n as usize + sector * size / sectors as usize) % size]
+    };
+    match settings.colouring {
+        Colouring::Iterations | Colouring::AtomDomain => PALETTE[n as usize % size],
+        Colouring::BinaryDecomposition => decomposition(2),
+        Colouring::Decomposition => decomposition(settings.sectors),
+        Colouring::AngleHue => hsv(turn(z), 1.0, 1.0),
+        Colouring::AnglePalette => PALETTE[(turn(z) * size as f64) as usize % size],
+    }
+}
+
+// Packs hue (in turns), saturation and value into the canvas' 0xAABBGGRR layout
+pub(crate) fn hsv(hue: f64, saturation: f64, value: f64) -> u32 {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let c = value * saturation;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = value - c;
+    rgb(r + m, g + m, b + m)
+}
+
+pub(crate) fn rgb(r: f64, g: f64, b: f64) -> u32 {
+    let byte = |v: f64| (255.0 * v.clamp(0.0, 1.0)).round() as u32;
+    255 << 24 | byte(b) << 16 | byte(g) << 8 | byte(r)
+}
+
+// Converts CSS style 0xRRGGBB into the canvas' pixel layout
+pub(crate) fn from_hex(rgb: u32) -> u32 {
+    255 << 24 | (rgb & 0xff) << 16 | (rgb & 0xff00) | (rgb >> 16 & 0xff)
+}
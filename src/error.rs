@@ -0,0 +1,56 @@
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    NonFinitePoint,
+    PointOutsideCanvas,
+    ZoomOutLimit,
+    ZoomInLimit,
+    OutOfBounds,
+    DegenerateRange,
+    InvalidSetting(&'static str),
+    InvalidPolynomial(&'static str),
+    InvalidSequence(&'static str),
+    // Byte offset into the formula source and reason
+    Parse(usize, &'static str),
+    NotFound(&'static str),
+    InvalidArgument(&'static str),
+    UnsupportedFractal,
+    InvalidImage(&'static str),
+    WriteFailed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NonFinitePoint => write!(f, "point coordinates must be finite"),
+            Error::PointOutsideCanvas => write!(f, "point lies outside the canvas"),
+            Error::ZoomOutLimit => write!(f, "cannot zoom out any further"),
+            Error::ZoomInLimit => write!(f, "cannot zoom in any further: floating point precision exhausted"),
+            Error::OutOfBounds => write!(f, "view would move too far away from the Mandelbrot set"),
+            Error::DegenerateRange => write!(f, "plot range is degenerate"),
+            Error::InvalidSetting(reason) => write!(f, "invalid setting: {}", reason),
+            Error::InvalidPolynomial(reason) => write!(f, "invalid polynomial: {}", reason),
+            Error::InvalidSequence(reason) => write!(f, "invalid Lyapunov sequence: {}", reason),
+            Error::Parse(position, reason) => write!(f, "parse error at position {}: {}", position, reason),
+            Error::NotFound(reason) => write!(f, "not found: {}", reason),
+            Error::InvalidArgument(reason) => write!(f, "invalid argument: {}", reason),
+            Error::UnsupportedFractal => write!(f, "not supported for the current fractal"),
+            Error::InvalidImage(reason) => write!(f, "invalid image: {}", reason),
+            Error::WriteFailed => write!(f, "could not write the image"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for JsValue {
+    fn from(error: Error) -> JsValue {
+        let exception = js_sys::Error::new(&error.to_string());
+        if let Error::Parse(position, _) = error {
+            let _ = js_sys::Reflect::set(&exception, &"position".into(), &(position as u32).into());
+        }
+        exception.into()
+    }
+}
@@ -0,0 +1,488 @@
+use num::Complex;
+
+use crate::render::Dwell;
+use crate::{escape, smooth_iteration, ApplicationSettings, Error, DEGREE};
+
+const MAX_LENGTH: usize = 256;
+const MAX_STACK: usize = 32;
+// Integer exponents up to this size are evaluated by repeated squaring instead of powc
+const MAX_INTEGER_POWER: f64 = 64.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Log,
+    Sqrt,
+    Conj,
+    Abs,
+    Re,
+    Im
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Function> {
+        Some(match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "tanh" => Function::Tanh,
+            "exp" => Function::Exp,
+            "log" | "ln" => Function::Log,
+            "sqrt" => Function::Sqrt,
+            "conj" => Function::Conj,
+            "abs" => Function::Abs,
+            "re" | "real" => Function::Re,
+            "im" | "imag" => Function::Im,
+            _ => return None
+        })
+    }
+    fn apply(self, z: Complex<f64>) -> Complex<f64> {
+        match self {
+            Function::Sin => z.sin(),
+            Function::Cos => z.cos(),
+            Function::Tan => z.tan(),
+            Function::Sinh => z.sinh(),
+            Function::Cosh => z.cosh(),
+            Function::Tanh => z.tanh(),
+            Function::Exp => z.exp(),
+            Function::Log => z.ln(),
+            Function::Sqrt => z.sqrt(),
+            Function::Conj => z.conj(),
+            Function::Abs => Complex::new(z.norm(), 0.0),
+            Function::Re => Complex::new(z.re, 0.0),
+            Function::Im => Complex::new(z.im, 0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow
+}
+
+impl Operator {
+    fn apply(self, a: Complex<f64>, b: Complex<f64>) -> Complex<f64> {
+        match self {
+            Operator::Add => a + b,
+            Operator::Sub => a - b,
+            Operator::Mul => a * b,
+            Operator::Div => a / b,
+            Operator::Pow => a.powc(b),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expression {
+    Z,
+    C,
+    Constant(Complex<f64>),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+    Call(Function, Box<Expression>)
+}
+
+impl Expression {
+    // Constant folding, so that e.g. `2*pi*i` costs a single push
+    fn binary(operator: Operator, a: Expression, b: Expression) -> Expression {
+        match (&a, &b) {
+            (Expression::Constant(x), Expression::Constant(y)) => Expression::Constant(operator.apply(*x, *y)),
+            _ => Expression::Binary(operator, Box::new(a), Box::new(b))
+        }
+    }
+    fn negate(a: Expression) -> Expression {
+        match a {
+            Expression::Constant(x) => Expression::Constant(-x),
+            _ => Expression::Negate(Box::new(a))
+        }
+    }
+    fn call(function: Function, a: Expression) -> Expression {
+        match a {
+            Expression::Constant(x) => Expression::Constant(function.apply(x)),
+            _ => Expression::Call(function, Box::new(a))
+        }
+    }
+    // Polynomial degree in z, if the expression is a polynomial in z
+    fn degree(&self) -> Option<f64> {
+        match self {
+            Expression::Z => Some(1.0),
+            Expression::C | Expression::Constant(_) => Some(0.0),
+            Expression::Negate(a) => a.degree(),
+            Expression::Binary(operator, a, b) => {
+                let (m, n) = (a.degree()?, b.degree()?);
+                match operator {
+                    Operator::Add | Operator::Sub => Some(m.max(n)),
+                    Operator::Mul => Some(m + n),
+                    Operator::Div if n == 0.0 => Some(m),
+                    Operator::Pow => match **b {
+                        Expression::Constant(k) if k.im == 0.0 && k.re >= 0.0 => Some(m * k.re),
+                        _ if m == 0.0 && n == 0.0 => Some(0.0),
+                        _ => None
+                    },
+                    _ => None
+                }
+            }
+            Expression::Call(_, a) => Some(a.degree()?).filter(|&d| d == 0.0),
+        }
+    }
+    // Evaluation stack entries needed by the compiled code, mirroring `compile`
+    fn stack(&self) -> usize {
+        match self {
+            Expression::Z | Expression::C | Expression::Constant(_) => 1,
+            Expression::Negate(a) | Expression::Call(_, a) => a.stack(),
+            Expression::Binary(Operator::Pow, a, b) if Expression::integer_power(b).is_some() => a.stack(),
+            Expression::Binary(_, a, b) => a.stack().max(1 + b.stack()),
+        }
+    }
+    // Exponents compiled to repeated squaring
+    fn integer_power(b: &Expression) -> Option<i32> {
+        match *b {
+            Expression::Constant(k) if k.im == 0.0 && k.re.fract() == 0.0 && k.re.abs() <= MAX_INTEGER_POWER => {
+                Some(k.re as i32)
+            }
+            _ => None
+        }
+    }
+    fn compile(&self, code: &mut Vec<Op>) {
+        match self {
+            Expression::Z => code.push(Op::Z),
+            Expression::C => code.push(Op::C),
+            Expression::Constant(k) => code.push(Op::Constant(*k)),
+            Expression::Negate(a) => {
+                a.compile(code);
+                code.push(Op::Negate);
+            }
+            Expression::Binary(Operator::Pow, a, b) => {
+                a.compile(code);
+                match Expression::integer_power(b) {
+                    Some(n) => code.push(Op::PowI(n)),
+                    None => {
+                        b.compile(code);
+                        code.push(Op::Binary(Operator::Pow));
+                    }
+                }
+            }
+            Expression::Binary(operator, a, b) => {
+                a.compile(code);
+                b.compile(code);
+                code.push(Op::Binary(*operator));
+            }
+            Expression::Call(function, a) => {
+                a.compile(code);
+                code.push(Op::Call(*function));
+            }
+        }
+    }
+}
+
+// Instructions of the stack machine evaluating a compiled formula
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Z,
+    C,
+    Constant(Complex<f64>),
+    Negate,
+    Binary(Operator),
+    PowI(i32),
+    Call(Function)
+}
+
+struct Parser<'a> {
+    source: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Option<u8> {
+        while self.position < self.source.len() && self.source[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        self.source.get(self.position).copied()
+    }
+    // Offset of the next token
+    fn offset(&mut self) -> usize {
+        self.peek();
+        self.position
+    }
+    // Combines two operands, `b` starting at `start`, unless the result would overflow
+    // the evaluation stack
+    fn binary(&self, operator: Operator, a: Expression, b: Expression, start: usize) -> Result<Expression, Error> {
+        let expression = Expression::binary(operator, a, b);
+        if expression.stack() > MAX_STACK {
+            return Err(Error::Parse(start, "formula too deeply nested"));
+        }
+        Ok(expression)
+    }
+    fn accept(&mut self, token: u8) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+    // sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<Expression, Error> {
+        let mut a = self.product()?;
+        loop {
+            let operator = if self.accept(b'+') {
+                Operator::Add
+            } else if self.accept(b'-') {
+                Operator::Sub
+            } else {
+                return Ok(a);
+            };
+            let start = self.offset();
+            let b = self.product()?;
+            a = self.binary(operator, a, b, start)?;
+        }
+    }
+    // product := unary (('*' | '/') unary)*
+    fn product(&mut self) -> Result<Expression, Error> {
+        let mut a = self.unary()?;
+        loop {
+            let operator = if self.accept(b'*') {
+                Operator::Mul
+            } else if self.accept(b'/') {
+                Operator::Div
+            } else {
+                return Ok(a);
+            };
+            let start = self.offset();
+            let b = self.unary()?;
+            a = self.binary(operator, a, b, start)?;
+        }
+    }
+    // unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<Expression, Error> {
+        if self.accept(b'-') {
+            return Ok(Expression::negate(self.unary()?));
+        }
+        if self.accept(b'+') {
+            return self.unary();
+        }
+        self.power()
+    }
+    // power := primary ('^' unary)?, binding to the right
+    fn power(&mut self) -> Result<Expression, Error> {
+        let base = self.primary()?;
+        if self.accept(b'^') {
+            let start = self.offset();
+            let exponent = self.unary()?;
+            return self.binary(Operator::Pow, base, exponent, start);
+        }
+        Ok(base)
+    }
+    // primary := number | variable | constant | function '(' sum ')' | '(' sum ')'
+    fn primary(&mut self) -> Result<Expression, Error> {
+        let start = match self.peek() {
+            Some(_) => self.position,
+            None => return Err(Error::Parse(self.position, "unexpected end of formula"))
+        };
+        if self.accept(b'(') {
+            let inner = self.sum()?;
+            if !self.accept(b')') {
+                return Err(Error::Parse(self.position, "expected ')'"));
+            }
+            return Ok(inner);
+        }
+        let next = self.source[start];
+        if next.is_ascii_digit() || next == b'.' {
+            return self.number();
+        }
+        if !next.is_ascii_alphabetic() {
+            return Err(Error::Parse(start, "expected a number, variable, function or '('"));
+        }
+        while self.position < self.source.len()
+              && (self.source[self.position].is_ascii_alphanumeric() || self.source[self.position] == b'_') {
+            self.position += 1;
+        }
+        let name = std::str::from_utf8(&self.source[start..self.position])
+            .map_err(|_| Error::Parse(start, "invalid identifier"))?
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "z" => return Ok(Expression::Z),
+            "c" => return Ok(Expression::C),
+            "i" => return Ok(Expression::Constant(Complex::new(0.0, 1.0))),
+            "pi" => return Ok(Expression::Constant(Complex::new(std::f64::consts::PI, 0.0))),
+            _ => {}
+        }
+        let function = Function::from_name(&name).ok_or(Error::Parse(start, "unknown variable or function"))?;
+        if !self.accept(b'(') {
+            return Err(Error::Parse(self.position, "expected '(' after function name"));
+        }
+        let argument = self.sum()?;
+        if !self.accept(b')') {
+            return Err(Error::Parse(self.position, "expected ')'"));
+        }
+        Ok(Expression::call(function, argument))
+    }
+    fn number(&mut self) -> Result<Expression, Error> {
+        let start = self.position;
+        let digits = |parser: &mut Parser| {
+            while parser.position < parser.source.len() && parser.source[parser.position].is_ascii_digit() {
+                parser.position += 1;
+            }
+        };
+        digits(self);
+        if self.source.get(self.position) == Some(&b'.') {
+            self.position += 1;
+            digits(self);
+        }
+        if let Some(b'e') | Some(b'E') = self.source.get(self.position) {
+            self.position += 1;
+            if let Some(b'+') | Some(b'-') = self.source.get(self.position) {
+                self.position += 1;
+            }
+            digits(self);
+        }
+        std::str::from_utf8(&self.source[start..self.position]).ok()
+            .and_then(|literal| literal.parse::<f64>().ok())
+            .map(|value| Expression::Constant(Complex::new(value, 0.0)))
+            .ok_or(Error::Parse(start, "malformed number"))
+    }
+}
+
+// User-defined iteration z -> f(z, c), starting from z = c, compiled to stack machine code
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Formula {
+    source: String,
+    code: Vec<Op>,
+    degree: f64,
+}
+
+impl Default for Formula {
+    fn default() -> Self {
+        Formula::compile("z^2 + c").unwrap()
+    }
+}
+
+impl Formula {
+    pub fn compile(source: &str) -> Result<Formula, Error> {
+        if source.len() > MAX_LENGTH {
+            return Err(Error::Parse(MAX_LENGTH, "formula too long"));
+        }
+        if let Some(position) = source.bytes().position(|b| !b.is_ascii()) {
+            return Err(Error::Parse(position, "unexpected character"));
+        }
+        let mut parser = Parser { source: source.as_bytes(), position: 0 };
+        let expression = parser.sum()?;
+        if parser.peek().is_some() {
+            return Err(Error::Parse(parser.position, "unexpected character"));
+        }
+        // The parser keeps the stack within MAX_STACK
+        let mut code = Vec::new();
+        expression.compile(&mut code);
+        // Smooth colouring needs an escape rate; assume quadratic for non-polynomial formulas
+        let degree = expression.degree().filter(|&d| d > 1.0).unwrap_or(DEGREE);
+        Ok(Formula { source: source.to_string(), code, degree })
+    }
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    pub fn evaluate(&self, z: &Complex<f64>, c: &Complex<f64>) -> Complex<f64> {
+        let mut stack = [Complex::new(0.0, 0.0); MAX_STACK];
+        let mut top = 0;
+        for op in self.code.iter() {
+            match *op {
+                Op::Z => {
+                    stack[top] = *z;
+                    top += 1;
+                }
+                Op::C => {
+                    stack[top] = *c;
+                    top += 1;
+                }
+                Op::Constant(k) => {
+                    stack[top] = k;
+                    top += 1;
+                }
+                Op::Negate => stack[top - 1] = -stack[top - 1],
+                Op::Binary(operator) => {
+                    top -= 1;
+                    stack[top - 1] = operator.apply(stack[top - 1], stack[top]);
+                }
+                Op::PowI(2) => stack[top - 1] = stack[top - 1] * stack[top - 1],
+                Op::PowI(n) => stack[top - 1] = stack[top - 1].powi(n),
+                Op::Call(function) => stack[top - 1] = function.apply(stack[top - 1]),
+            }
+        }
+        stack[0]
+    }
+    pub fn escape_time(&self, c: &Complex<f64>, settings: &ApplicationSettings) -> Option<(f64, Complex<f64>)> {
+        escape(*c, |z| self.evaluate(z, c), settings, |_| {})
+            .map(|(i, modulus, z)| (smooth_iteration(i, modulus, settings.bailout, self.degree), z))
+    }
+    pub fn dwell(&self, c: &Complex<f64>, settings: &ApplicationSettings) -> Dwell {
+        match self.escape_time(c, settings) {
+            Some((tau, z)) => Dwell::escaped(tau, &z, settings),
+            None => Dwell::Bounded
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(source: &str) -> Option<usize> {
+        match Formula::compile(source) {
+            Err(Error::Parse(position, _)) => Some(position),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(position("z^2 + "), Some(6));
+        assert_eq!(position("z^2 + q"), Some(6));
+        assert_eq!(position("sin z"), Some(4));
+        assert_eq!(position("(z + c"), Some(6));
+        assert_eq!(position("z $ c"), Some(2));
+        assert_eq!(position("z^2 + c)"), Some(7));
+        assert_eq!(position("z * é"), Some(4));
+        assert_eq!(position(&"z+".repeat(200)), Some(MAX_LENGTH));
+    }
+
+    #[test]
+    fn nesting_errors_point_at_the_overflowing_operand() {
+        // Every level keeps one more operand on the stack
+        let nested = |levels: usize| format!("{}z{}", "z + (".repeat(levels), ")".repeat(levels));
+        assert!(Formula::compile(&nested(MAX_STACK - 1)).is_ok());
+        assert_eq!(position(&nested(MAX_STACK)), Some(4));
+        assert_eq!(position(&format!("c * ({})", nested(MAX_STACK - 1))), Some(4));
+        // Integer powers are squared in place and need no stack entry
+        assert!(Formula::compile(&format!("{}z{}", "(".repeat(40), ")^2".repeat(40))).is_ok());
+    }
+
+    #[test]
+    fn default_formula_follows_the_quadratic_loop() {
+        let formula = Formula::default();
+        let settings = ApplicationSettings::default();
+        for &(re, im) in [(-0.75, 0.1), (0.3, 0.5), (-1.5, 0.0), (0.26, 0.0), (-0.1, 0.9), (2.0, 2.0)].iter() {
+            let c = Complex::new(re, im);
+            let z = Complex::new(0.4, -0.3);
+            assert_eq!(formula.evaluate(&z, &c), z * z + c);
+            // The formula starts from z = c, one iteration ahead of the quadratic loop
+            match (formula.escape_time(&c, &settings), crate::escape_time(&c, &settings)) {
+                (Some((tau, w)), Some((sigma, v))) => {
+                    assert!((tau + 1.0 - sigma).abs() < 1e-9, "{} at {}", tau, c);
+                    assert_eq!(w, v);
+                }
+                (None, None) => {}
+                (tau, sigma) => panic!("{:?} and {:?} at {}", tau, sigma, c)
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+
+use crate::{ApplicationSettings, PlotRange};
+
+// Views remembered for going back; the oldest ones are dropped first
+const HISTORY_LENGTH: usize = 64;
+
+pub(crate) type View = (PlotRange, ApplicationSettings);
+
+// Undo/redo stacks of views. Recording a new view discards the views gone back from.
+#[derive(Default)]
+pub(crate) struct History {
+    past: VecDeque<View>,
+    future: Vec<View>,
+}
+
+impl History {
+    // Remembers the view left behind, unless nothing changed
+    pub fn record(&mut self, previous: View, current: View) {
+        if previous == current {
+            return;
+        }
+        if self.past.len() == HISTORY_LENGTH {
+            self.past.pop_front();
+        }
+        self.past.push_back(previous);
+        self.future.clear();
+    }
+    pub fn back(&mut self, current: View) -> Option<View> {
+        let view = self.past.pop_back()?;
+        self.future.push(current);
+        Some(view)
+    }
+    pub fn forward(&mut self, current: View) -> Option<View> {
+        let view = self.future.pop()?;
+        self.past.push_back(current);
+        Some(view)
+    }
+    pub fn len(&self) -> usize {
+        self.past.len()
+    }
+    pub fn clear(&mut self) {
+        self.past.clear();
+        self.future.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::Complex;
+
+    fn view(i: usize) -> View {
+        let range = PlotRange { top_left: Complex::new(i as f64, 1.0),
+                                bottom_right: Complex::new(i as f64 + 1.0, 0.0) };
+        (range, ApplicationSettings::default())
+    }
+
+    #[test]
+    fn keeps_the_latest_views() {
+        let mut history = History::default();
+        for i in 0..100 {
+            history.record(view(i), view(i + 1));
+        }
+        assert_eq!(history.len(), HISTORY_LENGTH);
+        let mut current = view(100);
+        for i in (100 - HISTORY_LENGTH..100).rev() {
+            let previous = history.back(current).unwrap();
+            assert_eq!(previous, view(i));
+            current = previous;
+        }
+        assert!(history.back(current).is_none());
+    }
+
+    #[test]
+    fn new_moves_drop_the_redo_views() {
+        let mut history = History::default();
+        history.record(view(0), view(1));
+        history.record(view(1), view(2));
+        assert_eq!(history.back(view(2)), Some(view(1)));
+        history.record(view(1), view(3));
+        assert!(history.forward(view(3)).is_none());
+        assert_eq!(history.back(view(3)), Some(view(1)));
+    }
+}
@@ -0,0 +1,156 @@
+// Raw per-pixel iteration data, for analysis or recolouring in other tools.
+//
+// Every pixel is a record of its continuous iteration count followed by a byte which
+// is 1 if the orbit escaped and 0 otherwise; pixels which did not escape carry
+// max_iterations. Records are packed, little-endian and in row-major order starting
+// from the top left corner of the view.
+//
+// The .npy variants store them as a structured array of shape (height, width) with
+// fields `iterations` and `escaped`, so that numpy.load reads them directly.
+//
+// The raw variants are preceded by a 72 byte little-endian header:
+//
+//   offset  type     content
+//        0  [u8; 8]  magic "MBITERS\0"
+//        8  u32      format version, currently 1
+//       12  u32      width in pixels
+//       16  u32      height in pixels
+//       20  u32      bytes per iteration count, 4 (f32) or 8 (f64)
+//       24  f64      real part of the view center
+//       32  f64      imaginary part of the view center
+//       40  f64      width of the view
+//       48  f64      height of the view
+//       56  f64      bailout radius
+//       64  u32      max_iterations
+//       68  u32      reserved, 0
+
+use std::io::{self, Write};
+use wasm_bindgen::prelude::*;
+
+use crate::{ApplicationSettings, PlotRange};
+
+const MAGIC: &[u8; 8] = b"MBITERS\0";
+const VERSION: u32 = 1;
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+// The npy header is padded to a multiple of this
+const NPY_ALIGNMENT: usize = 64;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataFormat {
+    NpyF32,
+    NpyF64,
+    RawF32,
+    RawF64
+}
+
+impl DataFormat {
+    fn precision(self) -> usize {
+        match self {
+            DataFormat::NpyF32 | DataFormat::RawF32 => 4,
+            DataFormat::NpyF64 | DataFormat::RawF64 => 8,
+        }
+    }
+}
+
+fn write_header<W: Write>(sink: &mut W, format: DataFormat, range: &PlotRange, width: usize, height: usize,
+                          settings: &ApplicationSettings) -> io::Result<()> {
+    match format {
+        DataFormat::NpyF32 | DataFormat::NpyF64 => {
+            let dictionary = format!("{{'descr': [('iterations', '<f{}'), ('escaped', '|u1')], \
+                                      'fortran_order': False, 'shape': ({}, {}), }}",
+                                     format.precision(), height, width);
+            // Magic, version and header length take 10 bytes, the header ends in a newline
+            let padding = NPY_ALIGNMENT - (10 + dictionary.len() + 1) % NPY_ALIGNMENT;
+            let header = format!("{}{}\n", dictionary, " ".repeat(padding % NPY_ALIGNMENT));
+            sink.write_all(NPY_MAGIC)?;
+            sink.write_all(&[1, 0])?;
+            sink.write_all(&(header.len() as u16).to_le_bytes())?;
+            sink.write_all(header.as_bytes())
+        }
+        DataFormat::RawF32 | DataFormat::RawF64 => {
+            let center = range.center();
+            sink.write_all(MAGIC)?;
+            for value in [VERSION, width as u32, height as u32, format.precision() as u32].iter() {
+                sink.write_all(&value.to_le_bytes())?;
+            }
+            for value in [center.re, center.im, range.width(), -range.height(), settings.bailout].iter() {
+                sink.write_all(&value.to_le_bytes())?;
+            }
+            sink.write_all(&settings.max_iterations.to_le_bytes())?;
+            sink.write_all(&0u32.to_le_bytes())
+        }
+    }
+}
+
+// Streams the iteration data of a width x height image of the range, row by row.
+// `escape` yields the continuous iteration count of escaping points.
+pub(crate) fn export<W, F>(mut sink: W, format: DataFormat, range: &PlotRange, width: usize, height: usize,
+                           settings: &ApplicationSettings, escape: F) -> io::Result<W>
+    where W: Write, F: Fn(&num::Complex<f64>) -> Option<f64> {
+    write_header(&mut sink, format, range, width, height, settings)?;
+    let mut row = Vec::with_capacity(width * (format.precision() + 1));
+    for y in 0..height {
+        row.clear();
+        for x in 0..width {
+            let c = range.pixel_to_point(x as f64, y as f64, width, height);
+            let (value, escaped) = match escape(&c) {
+                Some(tau) => (tau, 1),
+                None => (settings.max_iterations as f64, 0)
+            };
+            match format.precision() {
+                4 => row.extend_from_slice(&(value as f32).to_le_bytes()),
+                _ => row.extend_from_slice(&value.to_le_bytes()),
+            }
+            row.push(escaped);
+        }
+        sink.write_all(&row)?;
+    }
+    Ok(sink)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::Complex;
+    use std::convert::TryInto;
+
+    fn header(format: DataFormat, width: usize, height: usize) -> Vec<u8> {
+        let range = PlotRange { top_left: Complex::new(-2.0, 1.25), bottom_right: Complex::new(1.0, -1.25) };
+        let settings = ApplicationSettings { bailout: 1e300, ..ApplicationSettings::default() };
+        let mut bytes = Vec::new();
+        write_header(&mut bytes, format, &range, width, height, &settings).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn raw_header_layout() {
+        let bytes = header(DataFormat::RawF64, 800, 600);
+        let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let f64_at = |offset: usize| f64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        assert_eq!(bytes.len(), 72);
+        assert_eq!(&bytes[..8], MAGIC);
+        assert_eq!((u32_at(8), u32_at(12), u32_at(16), u32_at(20)), (VERSION, 800, 600, 8));
+        assert_eq!((f64_at(24), f64_at(32), f64_at(40), f64_at(48)), (-0.5, 0.0, 3.0, 2.5));
+        assert_eq!(f64_at(56), 1e300);
+        assert_eq!((u32_at(64), u32_at(68)), (ApplicationSettings::default().max_iterations, 0));
+        assert_eq!(header(DataFormat::RawF32, 1, 1)[20], 4);
+    }
+
+    #[test]
+    fn npy_header_alignment() {
+        for &(width, height) in [(1, 1), (800, 600), (123_456, 7), (65_536, 65_536)].iter() {
+            for &format in [DataFormat::NpyF32, DataFormat::NpyF64].iter() {
+                let bytes = header(format, width, height);
+                assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+                let length = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+                assert_eq!(bytes.len(), 10 + length);
+                assert_eq!(bytes.len() % NPY_ALIGNMENT, 0);
+                assert_eq!(bytes.last(), Some(&b'\n'));
+                let dictionary = std::str::from_utf8(&bytes[10..]).unwrap();
+                assert!(dictionary.contains(&format!("'shape': ({}, {})", height, width)));
+                assert!(dictionary.contains(&format!("'<f{}'", format.precision())));
+            }
+        }
+    }
+}
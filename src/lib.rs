@@ -1,6 +1,46 @@
 use num::Complex;
+use std::cell::Cell;
+use std::io::{BufWriter, Write};
 use wasm_bindgen::prelude::*;
 
+mod area;
+mod atoms;
+mod buddhabrot;
+mod colour;
+mod error;
+mod formula;
+mod history;
+mod iterations;
+mod lines;
+mod lyapunov;
+mod misiurewicz;
+mod newton;
+mod nucleus;
+mod orbit;
+mod overlay;
+mod parameters;
+mod png;
+mod poster;
+mod rays;
+mod render;
+mod rng;
+
+pub use area::AreaEstimate;
+pub use colour::Colouring;
+pub use error::Error;
+pub use iterations::DataFormat;
+pub use lines::Lines;
+pub use render::{RenderStats, RenderStrategy};
+use buddhabrot::Buddhabrot;
+use formula::Formula;
+use history::{History, View};
+use lyapunov::Lyapunov;
+use misiurewicz::Misiurewicz;
+use newton::Newton;
+use nucleus::Nucleus;
+use orbit::Orbit;
+use render::{Dwell, Frame, ProveFn};
+
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
 #[cfg(feature = "wee_alloc")]
@@ -8,36 +48,111 @@ use wasm_bindgen::prelude::*;
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 const MAX_ITERATIONS: u32 = 512;
+const MIN_ITERATIONS: u32 = 64;
+const ITERATION_CEILING: u32 = 1 << 16;
+// Highest ceiling a user may choose; beyond this a single frame takes hours
+const MAX_ITERATION_CEILING: u32 = 1 << 20;
+// Additional iterations per halving of the view span, relative to MAX_ITERATIONS
+const ITERATION_GROWTH: f64 = 0.5;
 const WIDTH: usize = 800;
 const HEIGHT: usize = 600;
 const START_RANGE: PlotRange = PlotRange { top_left: Complex {re: -2.0, im: 1.25},
                                            bottom_right: Complex {re: 1.0, im: -1.25}};
+const NEWTON_RANGE: PlotRange = PlotRange { top_left: Complex {re: -2.0, im: 1.5},
+                                            bottom_right: Complex {re: 2.0, im: -1.5}};
+// Growth rates a (real part) and b (imaginary part) of the Lyapunov fractal
+const LYAPUNOV_RANGE: PlotRange = PlotRange { top_left: Complex {re: 2.0, im: 4.0},
+                                              bottom_right: Complex {re: 4.0, im: 2.5}};
 const ZOOM: f64 = 2.0;
+const BAILOUT: f64 = 256.0;
+// Orbits escaping "sideways" under a partial metric still escape once they blow up
+const ESCAPE_GUARD: f64 = 1e100;
+const DEGREE: f64 = 2.0;
 const STEP_SIZE: f64 = 0.05;
+// The view may neither grow beyond MAX_SPAN nor wander further than MAX_SPAN from the origin
+const MAX_SPAN: f64 = 16.0;
+// Minimal distance of adjacent pixels, in units of machine epsilon at the view center
+const MIN_RESOLUTION: f64 = 8.0;
+const SAMPLES: u32 = 100_000;
+// Highest number of Buddhabrot orbits per frame; each takes up to max_iterations steps
+const MAX_SAMPLES: u32 = 1 << 24;
+const SECTORS: u32 = 4;
 
-fn escape_time(c: &Complex<f64>, settings: &ApplicationSettings) -> Option<f64> {
-    if in_mandelbrot_set(&c) {
-        return None;
-    }
-    let mut z = Complex {re: 0.0, im: 0.0};
+// Continuous iteration count and final iterate of an escaping orbit
+fn escape_time(c: &Complex<f64>, settings: &ApplicationSettings) -> Option<(f64, Complex<f64>)> {
+    iterate(c, settings, |_| {})
+        .map(|(i, modulus, z)| (smooth_iteration(i, modulus, settings.bailout, DEGREE), z))
+}
+
+// Iterates z -> z^2 + c from z = 0, passing every iterate to `visit`. Returns the
+// iteration, modulus and iterate at escape, or None if the orbit stays bounded.
+fn iterate<F: FnMut(&Complex<f64>)>(c: &Complex<f64>, settings: &ApplicationSettings,
+                                    visit: F) -> Option<(u32, f64, Complex<f64>)> {
+    escape(Complex {re: 0.0, im: 0.0}, |z| z * z + c, settings, visit)
+}
+
+// Escape loop of an arbitrary map, shared by the quadratic iteration and user formulas
+fn escape<M, F>(mut z: Complex<f64>, map: M, settings: &ApplicationSettings,
+                mut visit: F) -> Option<(u32, f64, Complex<f64>)>
+    where M: Fn(&Complex<f64>) -> Complex<f64>, F: FnMut(&Complex<f64>) {
     for i in 0..settings.max_iterations {
-        z = z * z + c;
-        if z.norm_sqr() > (1 << 16) as f64 {
-            let shade = 1.0 - 0.01 * (z.norm_sqr().log2() / 2.0).log2();
-            return Some((i as f64) + shade)
+        z = map(&z);
+        visit(&z);
+        let modulus = settings.metric.norm(&z);
+        if modulus > settings.bailout {
+            return Some((i, modulus, z))
+        }
+        if z.norm_sqr() > ESCAPE_GUARD {
+            return Some((i, z.norm(), z))
         }
-        // return Some(500.0)
     }
     None
 }
 
+// Continuous iteration count for an orbit escaping at iteration `i` with
+// `modulus` > `bailout`: lies in [i, i + 1) for a map of the given degree.
+fn smooth_iteration(i: u32, modulus: f64, bailout: f64, degree: f64) -> f64 {
+    let shade = 1.0 - (modulus.ln() / bailout.ln()).ln() / degree.ln();
+    (i as f64 + shade).max(0.0)
+}
+
 fn in_mandelbrot_set(c: &Complex<f64>) -> bool {
-    (c - Complex::new(-1., 0.)).norm_sqr() < 0.0625 || {
+    // The origin has no direction to normalise, but lies inside the cardioid
+    (c - Complex::new(-1., 0.)).norm_sqr() < 0.0625 || c.norm_sqr() == 0. || {
     let z = c / c.norm_sqr().sqrt();
     c.norm_sqr() < (z / 2. - (z * z) / 4.).norm_sqr()
    }
 }
 
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fractal {
+    Mandelbrot,
+    Buddhabrot,
+    Nebulabrot,
+    AntiBuddhabrot,
+    Newton,
+    Lyapunov,
+    Formula
+}
+
+impl Fractal {
+    // Whether the view is the parameter plane of z -> z^2 + c
+    fn is_mandelbrot(self) -> bool {
+        match self {
+            Fractal::Mandelbrot | Fractal::Buddhabrot | Fractal::Nebulabrot | Fractal::AntiBuddhabrot => true,
+            Fractal::Newton | Fractal::Lyapunov | Fractal::Formula => false
+        }
+    }
+    fn start_range(self) -> PlotRange {
+        match self {
+            Fractal::Newton => NEWTON_RANGE,
+            Fractal::Lyapunov => LYAPUNOV_RANGE,
+            _ => START_RANGE
+        }
+    }
+}
+
 #[wasm_bindgen]
 pub enum Key {
     Up,
@@ -46,6 +161,28 @@ pub enum Key {
     Right
 }
 
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EscapeMetric {
+    Euclidean,
+    Manhattan,
+    Maximum,
+    Real,
+    Imaginary
+}
+
+impl EscapeMetric {
+    fn norm(self, z: &Complex<f64>) -> f64 {
+        match self {
+            EscapeMetric::Euclidean => z.norm(),
+            EscapeMetric::Manhattan => z.re.abs() + z.im.abs(),
+            EscapeMetric::Maximum => z.re.abs().max(z.im.abs()),
+            EscapeMetric::Real => z.re.abs(),
+            EscapeMetric::Imaginary => z.im.abs(),
+        }
+    }
+}
+
 #[wasm_bindgen]
 pub struct Point (f64, f64);
 
@@ -54,20 +191,162 @@ impl Point {
     pub fn new(x: f64, y: f64) -> Point {
         Point(x, y)
     }
+    pub fn x(&self) -> f64 {
+        self.0
+    }
+    pub fn y(&self) -> f64 {
+        self.1
+    }
+}
+
+// Position in the complex plane, as handed out to JS
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coordinates {
+    pub re: f64,
+    pub im: f64,
 }
 
+// Nucleus of a hyperbolic component and the approximate radius of its minibrot
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Component {
+    pub re: f64,
+    pub im: f64,
+    pub period: u32,
+    pub size: f64,
+}
 
+// Misiurewicz point and the width of a view framing its spirals
 #[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PreperiodicPoint {
+    pub re: f64,
+    pub im: f64,
+    pub preperiod: u32,
+    pub period: u32,
+    pub view_width: f64,
+}
+
+impl Point {
+    fn validate(&self) -> Result<(), Error> {
+        if !(self.0.is_finite() && self.1.is_finite()) {
+            return Err(Error::NonFinitePoint);
+        }
+        if self.0 < 0.0 || self.0 > WIDTH as f64 || self.1 < 0.0 || self.1 > HEIGHT as f64 {
+            return Err(Error::PointOutsideCanvas);
+        }
+        Ok(())
+    }
+}
+
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ApplicationSettings {
     zoom: f64,
     max_iterations: u32,
+    pub min_iterations: u32,
+    pub iteration_ceiling: u32,
+    pub bailout: f64,
+    pub metric: EscapeMetric,
+    // Orbit samples added per update in the Buddhabrot modes
+    pub samples: u32,
+    pub seed: u32,
+    pub lines: Lines,
+    // Draw the lines on a blank background instead of over the palette
+    pub lines_only: bool,
+    pub colouring: Colouring,
+    // Number of angular sectors of the n-way decomposition
+    pub sectors: u32,
+}
+
+impl Default for ApplicationSettings {
+    fn default() -> Self {
+        ApplicationSettings { zoom: ZOOM,
+                              max_iterations: MAX_ITERATIONS,
+                              min_iterations: MIN_ITERATIONS,
+                              iteration_ceiling: ITERATION_CEILING,
+                              bailout: BAILOUT,
+                              metric: EscapeMetric::Euclidean,
+                              samples: SAMPLES,
+                              seed: 0,
+                              lines: Lines::Off,
+                              lines_only: false,
+                              colouring: Colouring::Iterations,
+                              sectors: SECTORS }
+    }
+}
+
+#[wasm_bindgen]
+impl ApplicationSettings {
+    pub fn max_iterations(&self) -> u32 {
+        self.max_iterations
+    }
+}
+
+impl ApplicationSettings {
+    fn validate(&self) -> Result<(), Error> {
+        if !(self.zoom.is_finite() && self.zoom > 1.0) {
+            return Err(Error::InvalidSetting("zoom factor must be finite and greater than 1"));
+        }
+        // Beyond the guard radius orbits would stop short of the bailout, which puts the
+        // continuous iteration count outside [i, i + 1)
+        if !(self.bailout > 1.0 && self.bailout * self.bailout < ESCAPE_GUARD) {
+            return Err(Error::InvalidSetting("bailout radius must lie between 1 and 1e50"));
+        }
+        if self.min_iterations == 0 || self.min_iterations > self.iteration_ceiling {
+            return Err(Error::InvalidSetting("iteration bounds must satisfy 0 < min_iterations <= iteration_ceiling"));
+        }
+        if self.iteration_ceiling > MAX_ITERATION_CEILING {
+            return Err(Error::InvalidSetting("iteration ceiling must not exceed 2^20"));
+        }
+        // Only reachable through restored images, the limit is not set directly
+        if self.max_iterations == 0 || self.max_iterations > MAX_ITERATION_CEILING {
+            return Err(Error::InvalidSetting("iteration limit must be between 1 and 2^20"));
+        }
+        if self.samples > MAX_SAMPLES {
+            return Err(Error::InvalidSetting("sample count must not exceed 2^24"));
+        }
+        // More sectors than palette entries would only repeat colours
+        if self.sectors < 2 || self.sectors as usize > PALETTE.len() {
+            return Err(Error::InvalidSetting("decomposition needs between 2 and 1024 sectors"));
+        }
+        Ok(())
+    }
+    // Derive the iteration limit from the zoom depth (number of halvings of the
+    // view span relative to START_RANGE), boosted by the fraction of pixels
+    // which hit the limit in the previous frame.
+    fn adapt_iterations(&mut self, depth: f64, saturation: f64) {
+        let base = MAX_ITERATIONS as f64 * (1.0 + ITERATION_GROWTH * depth.max(0.0));
+        let target = base * (1.0 + saturation);
+        let floor = self.min_iterations.min(self.iteration_ceiling);
+        self.max_iterations = (target.round() as u32).clamp(floor, self.iteration_ceiling);
+    }
 }
 
 #[wasm_bindgen]
 pub struct Application {
    plot_range: PlotRange,
    settings: ApplicationSettings,
-   buffer: Vec<u32>
+   buffer: Vec<u32>,
+   saturation: f64,
+   strategy: RenderStrategy,
+   stats: RenderStats,
+   fractal: Fractal,
+   buddhabrot: Buddhabrot,
+   newton: Newton,
+   lyapunov: Lyapunov,
+   formula: Formula,
+   // Atom domain period per pixel, if the last frame was coloured by atom domains
+   domains: Vec<u32>,
+   history: History,
+}
+
+impl Default for Application {
+    fn default() -> Self {
+        Application::new()
+    }
 }
 
 #[wasm_bindgen]
@@ -80,69 +359,494 @@ impl Application {
     }
     pub fn new() -> Application {
         let buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
-        let settings = ApplicationSettings {zoom: ZOOM, max_iterations: MAX_ITERATIONS};
         Application { plot_range: START_RANGE,
-                      settings: settings,
-                      buffer: buffer }
+                      settings: ApplicationSettings::default(),
+                      buffer,
+                      saturation: 0.0,
+                      strategy: RenderStrategy::BruteForce,
+                      stats: RenderStats::default(),
+                      fractal: Fractal::Mandelbrot,
+                      buddhabrot: Buddhabrot::new(WIDTH, HEIGHT),
+                      newton: Newton::default(),
+                      lyapunov: Lyapunov::default(),
+                      formula: Formula::default(),
+                      domains: Vec::new(),
+                      history: History::default() }
+    }
+    pub fn settings(&self) -> ApplicationSettings {
+        self.settings
+    }
+    pub fn set_settings(&mut self, settings: ApplicationSettings) -> Result<(), JsValue> {
+        settings.validate()?;
+        let previous = self.view();
+        // The limit only adapts when the view moves, but stays within the new bounds
+        let max_iterations = self.settings.max_iterations.clamp(settings.min_iterations, settings.iteration_ceiling);
+        self.settings = ApplicationSettings { max_iterations, ..settings };
+        self.navigated(previous);
+        self.update();
+        Ok(())
+    }
+    pub fn render_strategy(&self) -> RenderStrategy {
+        self.strategy
+    }
+    pub fn set_render_strategy(&mut self, strategy: RenderStrategy) {
+        self.strategy = strategy;
+        self.update();
+    }
+    // Number of pixels the last update actually iterated, as opposed to filled in
+    pub fn iterated_pixels(&self) -> u32 {
+        self.stats.iterated_pixels
+    }
+    pub fn render_stats(&self) -> RenderStats {
+        self.stats
+    }
+    pub fn fractal(&self) -> Fractal {
+        self.fractal
+    }
+    pub fn set_fractal(&mut self, fractal: Fractal) {
+        if fractal.start_range() != self.fractal.start_range() {
+            // Views of another plane make no sense to go back to
+            self.plot_range = fractal.start_range();
+            self.saturation = 0.0;
+            self.settings.adapt_iterations(self.plot_range.depth(), self.saturation);
+            self.history.clear();
+        }
+        self.fractal = fractal;
+        self.update();
+    }
+    // Roots as interleaved real and imaginary parts
+    pub fn set_newton_roots(&mut self, roots: &[f64]) -> Result<(), JsValue> {
+        self.newton = Newton::from_roots(roots)?;
+        self.update();
+        Ok(())
+    }
+    // Coefficients as interleaved real and imaginary parts, constant term first
+    pub fn set_newton_coefficients(&mut self, coefficients: &[f64]) -> Result<(), JsValue> {
+        self.newton = Newton::from_coefficients(coefficients)?;
+        self.update();
+        Ok(())
+    }
+    // Sequence of growth rates, e.g. "AABAB"
+    pub fn set_lyapunov_sequence(&mut self, sequence: &str) -> Result<(), JsValue> {
+        self.lyapunov = Lyapunov::new(sequence)?;
+        self.update();
+        Ok(())
+    }
+    pub fn formula(&self) -> String {
+        self.formula.source().to_string()
+    }
+    // Iteration z -> f(z, c) for the Formula fractal, e.g. "z^3 + c*sin(z)"
+    pub fn set_formula(&mut self, source: &str) -> Result<(), JsValue> {
+        self.formula = Formula::compile(source)?;
+        self.update();
+        Ok(())
+    }
+    // Total number of orbits sampled into the current Buddhabrot
+    pub fn samples(&self) -> f64 {
+        self.buddhabrot.samples() as f64
     }
+    // Successive updates of an unchanged view refine the Buddhabrot progressively
     pub fn update(&mut self) {
-        for (index, value) in self.buffer.iter_mut().enumerate() {
-            let z = self.plot_range.index_to_point(index);
-            *value = match escape_time(&z, &self.settings) {
-                Some(tau) => PALETTE[tau.floor() as usize % 1024],
-                None => 255 << 24
-            };
+        let start = render::now();
+        let mut buffer = std::mem::take(&mut self.buffer);
+        match self.fractal {
+            Fractal::Buddhabrot | Fractal::Nebulabrot | Fractal::AntiBuddhabrot => {
+                self.buddhabrot.accumulate(self.fractal, &self.plot_range, &self.settings);
+                self.buddhabrot.tone_map(&mut buffer);
+                self.stats = RenderStats::default();
+                self.domains.clear();
+            }
+            _ => {
+                let (stats, domains) = self.paint(&self.plot_range, WIDTH, HEIGHT, &mut buffer);
+                self.stats = stats;
+                self.domains = domains;
+                self.saturation = stats.saturated;
+            }
         }
+        self.buffer = buffer;
+        self.stats.wall_time = render::now() - start;
     }
     pub fn reset(&mut self) {
-        self.settings = ApplicationSettings {zoom: ZOOM, max_iterations: MAX_ITERATIONS};
-        self.plot_range = START_RANGE;
+        let previous = self.view();
+        self.settings = ApplicationSettings { zoom: ZOOM,
+                                              max_iterations: MAX_ITERATIONS,
+                                              ..self.settings };
+        self.plot_range = self.fractal.start_range();
         self.buffer = vec![0; WIDTH * HEIGHT];
+        self.saturation = 0.0;
+        self.buddhabrot.clear();
+        self.moved(previous);
         self.update();
     }
-    pub fn zoom(&mut self, point: Point, out: bool) {
-            self.plot_range.zoom(point, out, &mut self.settings);
-            self.update();
+    pub fn zoom(&mut self, point: Point, out: bool) -> Result<(), JsValue> {
+        let previous = self.view();
+        self.plot_range.zoom(point, out, &self.settings)?;
+        self.moved(previous);
+        self.update();
+        Ok(())
     }
-    pub fn shift(&mut self, direction: Key){
-        self.plot_range.shift(direction);
+    pub fn shift(&mut self, direction: Key) -> Result<(), JsValue> {
+        let previous = self.view();
+        self.plot_range.shift(direction)?;
+        self.moved(previous);
         self.update();
+        Ok(())
+    }
+    // Returns to the previous view; false if there is none
+    pub fn back(&mut self) -> bool {
+        match self.history.back(self.view()) {
+            Some(view) => { self.restore(view); true }
+            None => false
+        }
+    }
+    // Undoes the last `back`; false if there is nothing to redo
+    pub fn forward(&mut self) -> bool {
+        match self.history.forward(self.view()) {
+            Some(view) => { self.restore(view); true }
+            None => false
+        }
+    }
+    // Number of views `back` can return to
+    pub fn history_len(&self) -> u32 {
+        self.history.len() as u32
     }
     pub fn image_buffer(&self) -> *const u32 {
             self.buffer.as_ptr()
     }
+    // The current image as PNG, with the view state in its text chunks
+    pub fn png(&self) -> Vec<u8> {
+        let text = parameters::describe(self.fractal, &self.plot_range, &self.settings, self.formula.source(),
+                                        &self.newton, &self.lyapunov);
+        png::encode(&self.buffer, WIDTH, HEIGHT, &text)
+    }
+    // PNG export at sizes far beyond the canvas, e.g. for print. `sink` receives the
+    // file as a series of Uint8Arrays, `progress` the fraction of rows done. Widths are
+    // limited to 524288 pixels.
+    pub fn export_poster(&self, width: u32, height: u32, sink: &js_sys::Function,
+                         progress: &js_sys::Function) -> Result<(), JsValue> {
+        let report = |done: f64| { let _ = progress.call1(&JsValue::NULL, &done.into()); };
+        let mut writer = self.write_poster(BufWriter::with_capacity(1 << 20, poster::JsSink(sink)),
+                                           width, height, report)?;
+        writer.flush().map_err(|_| Error::WriteFailed)?;
+        Ok(())
+    }
+    // Continues from the view an image returned by `png` was saved at
+    pub fn load_from_png(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        Ok(self.restore_png(bytes)?)
+    }
+    // Continuous iteration count and escape flag of every pixel of the current view
+    pub fn iteration_data(&self, format: DataFormat) -> Result<Vec<u8>, JsValue> {
+        Ok(self.write_iterations(Vec::new(), WIDTH as u32, HEIGHT as u32, format)?)
+    }
+    // Complex number at canvas position (x, y); fractional positions address sub-pixel locations
+    pub fn pixel_to_complex(&self, x: f64, y: f64) -> Result<Coordinates, JsValue> {
+        if !(x.is_finite() && y.is_finite()) {
+            return Err(Error::NonFinitePoint.into());
+        }
+        let z = self.plot_range.pixel_to_point(x, y, WIDTH, HEIGHT);
+        Ok(Coordinates { re: z.re, im: z.im })
+    }
+    // Canvas position of the complex number re + im i, possibly outside the canvas
+    pub fn complex_to_pixel(&self, re: f64, im: f64) -> Result<Point, JsValue> {
+        if !(re.is_finite() && im.is_finite()) {
+            return Err(Error::NonFinitePoint.into());
+        }
+        let (x, y) = self.plot_range.point_to_pixel(&Complex { re, im }, WIDTH, HEIGHT);
+        Ok(Point(x, y))
+    }
+    // Atom domain period of every pixel in row-major order; empty unless the
+    // current image is coloured by atom domains
+    pub fn atom_domains(&self) -> Vec<u32> {
+        self.domains.clone()
+    }
+    // Area of the Mandelbrot set within [re_min, re_max] x [im_min, im_max], counting
+    // the members among the centers of a columns x rows grid at the current iteration limit
+    pub fn area_by_pixels(&self, re_min: f64, re_max: f64, im_min: f64, im_max: f64,
+                          columns: u32, rows: u32) -> Result<AreaEstimate, JsValue> {
+        let region = PlotRange::region(re_min, re_max, im_min, im_max);
+        Ok(area::pixel_count(&region, columns, rows, &self.settings)?)
+    }
+    // Same by uniform random sampling, seeded with the settings' seed
+    pub fn area_by_sampling(&self, re_min: f64, re_max: f64, im_min: f64, im_max: f64,
+                            samples: u32) -> Result<AreaEstimate, JsValue> {
+        let region = PlotRange::region(re_min, re_max, im_min, im_max);
+        Ok(area::monte_carlo(&region, samples, self.settings.seed as u64, &self.settings)?)
+    }
+    pub fn find_nucleus(&self, point: Point) -> Result<Component, JsValue> {
+        let nucleus = self.nucleus(&point)?;
+        Ok(Component { re: nucleus.c.re, im: nucleus.c.im, period: nucleus.period, size: nucleus.size.norm() })
+    }
+    // Frames the minibrot of the lowest period component near the canvas position
+    pub fn zoom_to_nucleus(&mut self, point: Point) -> Result<(), JsValue> {
+        let nucleus = self.nucleus(&point)?;
+        let previous = self.view();
+        self.plot_range.frame(&nucleus.c, &nucleus.size)?;
+        self.moved(previous);
+        self.update();
+        Ok(())
+    }
+    // Misiurewicz point of the given preperiod and period near the canvas position
+    pub fn find_misiurewicz(&self, point: Point, preperiod: u32, period: u32) -> Result<PreperiodicPoint, JsValue> {
+        let target = self.misiurewicz(&point, preperiod, period)?;
+        Ok(PreperiodicPoint { re: target.c.re,
+                              im: target.c.im,
+                              preperiod,
+                              period,
+                              view_width: START_RANGE.width() * target.scale })
+    }
+    pub fn zoom_to_misiurewicz(&mut self, point: Point, preperiod: u32, period: u32) -> Result<(), JsValue> {
+        let target = self.misiurewicz(&point, preperiod, period)?;
+        let previous = self.view();
+        self.plot_range.center_on(&target.c, target.scale)?;
+        self.moved(previous);
+        self.update();
+        Ok(())
+    }
+    // External ray of angle numerator/denominator turns, flattened as [re, im, re, im, ...]
+    // from the escape radius towards its landing point
+    pub fn external_ray(&self, numerator: u32, denominator: u32, max_points: u32) -> Result<Vec<f64>, JsValue> {
+        Ok(self.trace_ray(numerator, denominator, max_points)?.iter().flat_map(|c| vec![c.re, c.im]).collect())
+    }
+    // Draws the external ray over the current image, in CSS style 0xRRGGBB
+    pub fn draw_external_ray(&mut self, numerator: u32, denominator: u32, max_points: u32,
+                             colour: u32) -> Result<(), JsValue> {
+        let ray = self.trace_ray(numerator, denominator, max_points)?;
+        let pixels: Vec<(f64, f64)> = ray.iter().map(|c| self.plot_range.point_to_pixel(c, WIDTH, HEIGHT)).collect();
+        overlay::draw_polyline(&mut self.buffer, WIDTH, HEIGHT, &pixels, colour::from_hex(colour));
+        Ok(())
+    }
+    // Orbit of the point under the canvas position, flattened as
+    // [escape iteration or -1, period or 0, re(z1), im(z1), re(z2), im(z2), ...]
+    // with at most max_len iterates.
+    pub fn orbit(&self, point: Point, max_len: u32) -> Result<Vec<f64>, JsValue> {
+        if !self.fractal.is_mandelbrot() {
+            return Err(Error::UnsupportedFractal.into());
+        }
+        point.validate()?;
+        let c = self.plot_range.pixel_to_point(point.0, point.1, WIDTH, HEIGHT);
+        let orbit = Orbit::new(&c, &self.settings);
+        let mut flat = vec![orbit.escape.map_or(-1.0, |i| i as f64),
+                            orbit.period.map_or(0.0, |p| p as f64)];
+        for z in orbit.points.iter().take(max_len as usize) {
+            flat.push(z.re);
+            flat.push(z.im);
+        }
+        Ok(flat)
+    }
 }
 
+impl Application {
+    pub fn restore_png(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let saved = parameters::restore(&png::read_text(bytes)?)?;
+        saved.settings.validate()?;
+        saved.range.validate()?;
+        let formula = Formula::compile(&saved.formula)?;
+        let previous = self.view();
+        let same_plane = saved.fractal.start_range() == self.fractal.start_range();
+        self.fractal = saved.fractal;
+        self.formula = formula;
+        self.newton = saved.newton;
+        self.lyapunov = saved.lyapunov;
+        self.plot_range = saved.range;
+        self.settings = saved.settings;
+        if same_plane {
+            self.navigated(previous);
+        } else {
+            // Views of another plane make no sense to go back to
+            self.history.clear();
+        }
+        self.update();
+        Ok(())
+    }
+    // Streams the iteration data of a width x height image of the current view to
+    // `sink`, in one of the formats described in iterations.rs
+    pub fn write_iterations<W: Write>(&self, sink: W, width: u32, height: u32,
+                                      format: DataFormat) -> Result<W, Error> {
+        let settings = self.settings;
+        let formula = &self.formula;
+        let escape = |c: &Complex<f64>| match self.fractal {
+            Fractal::Formula => formula.escape_time(c, &settings).map(|(tau, _)| tau),
+            _ if in_mandelbrot_set(c) => None,
+            _ => escape_time(c, &settings).map(|(tau, _)| tau),
+        };
+        match self.fractal {
+            Fractal::Mandelbrot | Fractal::Formula => {
+                iterations::export(sink, format, &self.plot_range, width as usize, height as usize, &settings, escape)
+                    .map_err(|_| Error::WriteFailed)
+            }
+            _ => Err(Error::UnsupportedFractal)
+        }
+    }
+    // Streams a width x height PNG of the current view to `sink`
+    pub fn write_poster<W: Write, F: FnMut(f64)>(&self, sink: W, width: u32, height: u32,
+                                                 progress: F) -> Result<W, Error> {
+        if matches!(self.fractal, Fractal::Buddhabrot | Fractal::Nebulabrot | Fractal::AntiBuddhabrot) {
+            // The orbit histograms span the whole image and cannot be rendered in strips
+            return Err(Error::UnsupportedFractal);
+        }
+        if width == 0 || width as usize > poster::MAX_WIDTH {
+            return Err(Error::InvalidArgument("poster width must be between 1 and 524288"));
+        }
+        if height == 0 || height > i32::MAX as u32 {
+            return Err(Error::InvalidArgument("poster height must be between 1 and 2^31 - 1"));
+        }
+        let range = &self.plot_range;
+        let text = parameters::describe(self.fractal, range, &self.settings, self.formula.source(),
+                                        &self.newton, &self.lyapunov);
+        poster::export(sink, range, width as usize, height as usize, &text,
+                       |strip, w, h, buffer| { self.paint(strip, w, h, buffer); }, progress)
+            .map_err(|_| Error::WriteFailed)
+    }
+    fn nucleus(&self, point: &Point) -> Result<Nucleus, Error> {
+        if !self.fractal.is_mandelbrot() {
+            return Err(Error::UnsupportedFractal);
+        }
+        point.validate()?;
+        let c = self.plot_range.pixel_to_point(point.0, point.1, WIDTH, HEIGHT);
+        let pixel = (point.1 as usize).min(HEIGHT - 1) * WIDTH + (point.0 as usize).min(WIDTH - 1);
+        match self.domains.get(pixel) {
+            Some(&period) => Nucleus::of_period(&c, period),
+            None => Nucleus::find(&c, self.plot_range.width() * nucleus::BOX_FRACTION, &self.settings)
+        }
+    }
+    fn trace_ray(&self, numerator: u32, denominator: u32, max_points: u32) -> Result<Vec<Complex<f64>>, Error> {
+        if !self.fractal.is_mandelbrot() {
+            return Err(Error::UnsupportedFractal);
+        }
+        // Stop refining once the ray advances by less than a tenth of a pixel
+        let min_step = 0.1 * self.plot_range.width() / WIDTH as f64;
+        rays::external_ray(numerator, denominator, max_points, self.settings.max_iterations, min_step)
+    }
+    fn view(&self) -> View {
+        (self.plot_range, self.settings)
+    }
+    // Remembers `previous` for going back, if the view has since changed
+    fn navigated(&mut self, previous: View) {
+        let current = self.view();
+        self.history.record(previous, current);
+    }
+    // Records a move to a new plot range, whose iteration limit follows from its depth
+    // and the saturation of the frame left behind
+    fn moved(&mut self, previous: View) {
+        self.settings.adapt_iterations(self.plot_range.depth(), self.saturation);
+        self.navigated(previous);
+    }
+    fn restore(&mut self, view: View) {
+        let (plot_range, settings) = view;
+        self.plot_range = plot_range;
+        self.settings = settings;
+        self.update();
+    }
+    // Renders the view at width x height pixels, except for the Buddhabrot modes which
+    // accumulate over successive updates. Returns the frame statistics and, when
+    // coloured by atom domains, the domain periods.
+    fn paint(&self, range: &PlotRange, width: usize, height: usize, buffer: &mut [u32]) -> (RenderStats, Vec<u32>) {
+        let settings = self.settings;
+        let mut stats = RenderStats::default();
+        let mut domains = Vec::new();
+        let mut frame = None;
+        match self.fractal {
+            Fractal::Mandelbrot if settings.colouring == Colouring::AtomDomain => {
+                let (periods, atoms) = atoms::render(range, &settings, width, height, buffer);
+                let iterations = atoms.dwells.iter().map(|dwell| dwell.iterations(settings.max_iterations)).sum();
+                domains = periods;
+                stats = RenderStats::of(&atoms, iterations);
+                frame = Some(atoms);
+            }
+            Fractal::Mandelbrot | Fractal::Formula => {
+                let (dwell_stats, dwells) = self.render_dwells(range, width, height, buffer);
+                stats = dwell_stats;
+                frame = Some(dwells);
+            }
+            Fractal::Newton => {
+                self.newton.render(range, &settings, width, height, buffer);
+                stats.iterated_pixels = (width * height) as u32;
+            }
+            Fractal::Lyapunov => {
+                self.lyapunov.render(range, &settings, width, height, buffer);
+                stats.iterated_pixels = (width * height) as u32;
+            }
+            Fractal::Buddhabrot | Fractal::Nebulabrot | Fractal::AntiBuddhabrot => {}
+        }
+        // The lines run between the cells the dwells of the frame fall into
+        if let (Fractal::Mandelbrot, Some(frame)) = (self.fractal, &frame) {
+            lines::draw(settings.lines, settings.lines_only, &frame.dwells, width, height, buffer);
+        }
+        (stats, domains)
+    }
+    fn render_dwells(&self, range: &PlotRange, width: usize, height: usize,
+                     buffer: &mut [u32]) -> (RenderStats, Frame) {
+        let settings = self.settings;
+        let formula = &self.formula;
+        let iterations = Cell::new(0);
+        let count = |dwell: Dwell| {
+            iterations.set(iterations.get() + dwell.iterations(settings.max_iterations));
+            dwell
+        };
+        let prove = |a: &Complex<f64>, b: &Complex<f64>| {
+            let (dwell, steps) = Dwell::proven(a, b, &settings);
+            iterations.set(iterations.get() + steps);
+            dwell
+        };
+        // Formulas offer no bounds on their orbits, so every strategy iterates all pixels
+        let frame = match self.fractal {
+            Fractal::Formula => render::render(self.strategy, range, &|c| count(formula.dwell(c, &settings)),
+                                               None, width, height),
+            _ => {
+                let prove = Some(&prove as ProveFn).filter(|_| render::provable(&settings));
+                render::render(self.strategy, range, &|c| count(Dwell::of(c, &settings)), prove, width, height)
+            }
+        };
+        for (value, dwell) in buffer.iter_mut().zip(frame.dwells.iter()) {
+            *value = dwell.colour();
+        }
+        (RenderStats::of(&frame, iterations.get()), frame)
+    }
+    fn misiurewicz(&self, point: &Point, preperiod: u32, period: u32) -> Result<Misiurewicz, Error> {
+        if !self.fractal.is_mandelbrot() {
+            return Err(Error::UnsupportedFractal);
+        }
+        point.validate()?;
+        let c = self.plot_range.pixel_to_point(point.0, point.1, WIDTH, HEIGHT);
+        Misiurewicz::find(&c, preperiod, period)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct PlotRange {
     top_left: Complex<f64>,
     bottom_right: Complex<f64>
 }
 
 impl PlotRange {
-    pub fn index_to_point(&self, index: usize) -> Complex<f64> {
-        Complex {re: ((index % WIDTH) as f64) / (WIDTH as f64)
-                        * self.width() + self.top_left.re,
-                 im: (((index / WIDTH) as f64).floor()) / (HEIGHT as f64)
-                         * self.height() + self.top_left.im}
+    fn region(re_min: f64, re_max: f64, im_min: f64, im_max: f64) -> PlotRange {
+        PlotRange { top_left: Complex { re: re_min, im: im_max },
+                    bottom_right: Complex { re: re_max, im: im_min } }
+    }
+    pub fn pixel_to_point(&self, x: f64, y: f64, width: usize, height: usize) -> Complex<f64> {
+        Complex {re: x / (width as f64) * self.width() + self.top_left.re,
+                 im: y / (height as f64) * self.height() + self.top_left.im}
+    }
+    pub fn point_to_pixel(&self, z: &Complex<f64>, width: usize, height: usize) -> (f64, f64) {
+        ((z.re - self.top_left.re) / self.width() * (width as f64),
+         (z.im - self.top_left.im) / self.height() * (height as f64))
     }
-    pub fn zoom(&mut self, point: Point, out: bool, settings: &mut ApplicationSettings) {
+    pub fn zoom(&mut self, point: Point, out: bool, settings: &ApplicationSettings) -> Result<(), Error> {
+        point.validate()?;
         let h = self.height();
         let w = self.width();
-        let mut z = settings.zoom;
-        if out {
-            z = 1.0 / z;
-            settings.max_iterations -= 5;
-        } else {
-            settings.max_iterations += 5;
-        }
+        let z = if out { 1.0 / settings.zoom } else { settings.zoom };
         let mid_x = point.0 / (WIDTH as f64) * w + self.top_left.re;
         let mid_y = point.1 / (HEIGHT as f64) * h + self.top_left.im;
-        self.top_left = Complex {re: mid_x - w / (2.0 * z),
-                                 im: mid_y - h / (2.0 * z)};
-        self.bottom_right = Complex {re: mid_x + w / (2.0 * z),
-                                     im: mid_y + h / (2.0 * z)};
+        let zoomed = PlotRange { top_left: Complex {re: mid_x - w / (2.0 * z),
+                                                    im: mid_y - h / (2.0 * z)},
+                                 bottom_right: Complex {re: mid_x + w / (2.0 * z),
+                                                        im: mid_y + h / (2.0 * z)} };
+        zoomed.validate()?;
+        *self = zoomed;
+        Ok(())
     }
-    pub fn shift(&mut self, direction: Key) {
+    pub fn shift(&mut self, direction: Key) -> Result<(), Error> {
         let w = self.width() * STEP_SIZE;
         let delta = match direction {
             Key::Left => Complex {re: -w, im: 0.0},
@@ -150,8 +854,49 @@ impl PlotRange {
             Key::Up => Complex {re: 0.0, im: w},
             Key::Down => Complex {re: 0.0, im: -w},
         };
-        self.top_left += delta;
-        self.bottom_right += delta;
+        let shifted = PlotRange { top_left: self.top_left + delta,
+                                  bottom_right: self.bottom_right + delta };
+        shifted.validate()?;
+        *self = shifted;
+        Ok(())
+    }
+    pub fn validate(&self) -> Result<(), Error> {
+        let (w, h) = (self.width(), self.height());
+        if !(self.top_left.re.is_finite() && self.top_left.im.is_finite()
+             && self.bottom_right.re.is_finite() && self.bottom_right.im.is_finite()) {
+            return Err(Error::DegenerateRange);
+        }
+        if w <= 0.0 || h >= 0.0 {
+            return Err(Error::DegenerateRange);
+        }
+        if w > MAX_SPAN || -h > MAX_SPAN {
+            return Err(Error::ZoomOutLimit);
+        }
+        let center = self.center();
+        if center.re.abs() > MAX_SPAN || center.im.abs() > MAX_SPAN {
+            return Err(Error::OutOfBounds);
+        }
+        let resolution = MIN_RESOLUTION * f64::EPSILON * center.norm().max(1.0);
+        if w / (WIDTH as f64) < resolution || -h / (HEIGHT as f64) < resolution {
+            return Err(Error::ZoomInLimit);
+        }
+        Ok(())
+    }
+    // Shows the copy of START_RANGE scaled and rotated by `size` around `origin`; only
+    // the scale survives, as the view itself is always axis-aligned
+    pub fn frame(&mut self, origin: &Complex<f64>, size: &Complex<f64>) -> Result<(), Error> {
+        self.center_on(&(origin + size * START_RANGE.center()), size.norm())
+    }
+    // Shows START_RANGE scaled by `scale`, centered on `center`
+    pub fn center_on(&mut self, center: &Complex<f64>, scale: f64) -> Result<(), Error> {
+        let half = Complex { re: START_RANGE.width() * scale / 2.0, im: START_RANGE.height() * scale / 2.0 };
+        let framed = PlotRange { top_left: center - half, bottom_right: center + half };
+        framed.validate()?;
+        *self = framed;
+        Ok(())
+    }
+    pub fn center(&self) -> Complex<f64> {
+        (self.top_left + self.bottom_right) / 2.0
     }
     pub fn height(&self) -> f64 {
         self.bottom_right.im - self.top_left.im
@@ -159,6 +904,10 @@ impl PlotRange {
     pub fn width(&self) -> f64 {
         self.bottom_right.re - self.top_left.re
     }
+    // Number of halvings of the view span relative to START_RANGE
+    pub fn depth(&self) -> f64 {
+        (START_RANGE.width() / self.width()).log2()
+    }
 }
 
 const PALETTE: [u32; 1024] = [0xff640700, 0xff640700, 0xff650800, 0xff660800, 0xff670900, 0xff670a00, 0xff680a00, 0xff690b00, 
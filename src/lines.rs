@@ -0,0 +1,64 @@
+use num::Complex;
+use wasm_bindgen::prelude::*;
+
+use crate::colour;
+use crate::render::Dwell;
+
+// Distance of adjacent equipotentials, in units of the continuous iteration count
+const EQUIPOTENTIAL_SPACING: f64 = 0.5;
+// Field lines per dwell band; their number doubles with every further band
+const FIELD_LINES: u32 = 8;
+const LINE_COLOUR: u32 = 255 << 24;
+const BACKGROUND: u32 = 0xffff_ffff;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lines {
+    Off,
+    Equipotentials,
+    FieldLines,
+    Both
+}
+
+// Cell between the equipotentials and field lines of a point escaping with continuous
+// iteration count tau and final iterate z; 0 if no lines are drawn, so that the render
+// strategies fill regions as before
+pub(crate) fn cell(lines: Lines, tau: f64, z: &Complex<f64>) -> u32 {
+    if lines == Lines::Off {
+        return 0;
+    }
+    let level = (tau / EQUIPOTENTIAL_SPACING) as u32;
+    let sector = ((colour::turn(z) * FIELD_LINES as f64) as u32).min(FIELD_LINES - 1);
+    level * FIELD_LINES + sector
+}
+
+// Draws level lines of the potential and of the escape angle wherever they pass
+// between a pixel and its right or lower neighbour, either over the escape-time
+// image or, if lines_only, on a blank background
+pub(crate) fn draw(lines: Lines, lines_only: bool, dwells: &[Dwell], width: usize, height: usize,
+                   buffer: &mut [u32]) {
+    if lines == Lines::Off {
+        return;
+    }
+    let equipotentials = lines == Lines::Equipotentials || lines == Lines::Both;
+    let field_lines = lines == Lines::FieldLines || lines == Lines::Both;
+    let crosses = |a: Dwell, b: Dwell| match (a, b) {
+        (Dwell::Escaped(n, _, cell), Dwell::Escaped(m, _, other)) => {
+            (equipotentials && cell / FIELD_LINES != other / FIELD_LINES)
+            || (field_lines && n == m && cell % FIELD_LINES != other % FIELD_LINES)
+        }
+        _ => false
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            let line = (x + 1 < width && crosses(dwells[index], dwells[index + 1]))
+                       || (y + 1 < height && crosses(dwells[index], dwells[index + width]));
+            if line {
+                buffer[index] = LINE_COLOUR;
+            } else if lines_only && matches!(dwells[index], Dwell::Escaped(..)) {
+                buffer[index] = BACKGROUND;
+            }
+        }
+    }
+}
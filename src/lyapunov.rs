@@ -0,0 +1,67 @@
+use crate::colour::rgb;
+use crate::{ApplicationSettings, Error, PlotRange};
+
+const MAX_SEQUENCE: usize = 64;
+// Iterations discarded before the exponent is averaged, to let transients die out
+const WARMUP: u32 = 64;
+
+// Markus-Lyapunov fractal over the (a, b) parameter plane of the logistic map,
+// with the growth rate alternating between a and b according to the sequence
+pub(crate) struct Lyapunov {
+    // true selects b
+    sequence: Vec<bool>,
+}
+
+impl Default for Lyapunov {
+    fn default() -> Self {
+        Lyapunov { sequence: vec![false, true] }
+    }
+}
+
+impl Lyapunov {
+    pub fn new(sequence: &str) -> Result<Lyapunov, Error> {
+        if sequence.is_empty() || sequence.len() > MAX_SEQUENCE {
+            return Err(Error::InvalidSequence("length must be between 1 and 64"));
+        }
+        let sequence = sequence.chars()
+            .map(|c| match c {
+                'A' | 'a' => Ok(false),
+                'B' | 'b' => Ok(true),
+                _ => Err(Error::InvalidSequence("only the letters A and B are allowed"))
+            })
+            .collect::<Result<Vec<bool>, Error>>()?;
+        Ok(Lyapunov { sequence })
+    }
+    pub fn sequence(&self) -> String {
+        self.sequence.iter().map(|&is_b| if is_b { 'B' } else { 'A' }).collect()
+    }
+    pub fn exponent(&self, a: f64, b: f64, iterations: u32) -> f64 {
+        let rates = self.sequence.iter().map(|&is_b| if is_b { b } else { a }).cycle();
+        let mut x = 0.5;
+        let mut sum = 0.0;
+        for (n, r) in rates.take(WARMUP as usize + iterations as usize).enumerate() {
+            if n >= WARMUP as usize {
+                sum += (r * (1.0 - 2.0 * x)).abs().ln();
+            }
+            x = r * x * (1.0 - x);
+        }
+        sum / iterations as f64
+    }
+    // Stable (negative) exponents in yellow, chaotic (positive) ones in blue
+    pub fn render(&self, range: &PlotRange, settings: &ApplicationSettings,
+                  width: usize, height: usize, buffer: &mut [u32]) {
+        for (index, value) in buffer.iter_mut().enumerate() {
+            let p = range.pixel_to_point((index % width) as f64, (index / width) as f64, width, height);
+            let lambda = self.exponent(p.re, p.im, settings.max_iterations);
+            *value = if lambda.is_nan() || lambda == f64::INFINITY {
+                255 << 24
+            } else if lambda <= 0.0 {
+                let v = 1.0 - lambda.exp();
+                rgb(v, 0.85 * v, 0.0)
+            } else {
+                let v = 1.0 - (-2.0 * lambda).exp();
+                rgb(0.0, 0.3 * v, v)
+            };
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use num::Complex;
+
+use crate::Error;
+
+const NEWTON_STEPS: u32 = 64;
+const NEWTON_TOLERANCE: f64 = 1e-15;
+// Iterates closer than this are considered equal when verifying (pre)period
+const VERIFY_TOLERANCE: f64 = 1e-9;
+pub(crate) const MAX_ORDER: u32 = 4096;
+
+// Parameter whose critical orbit becomes periodic with the given period after
+// `preperiod` steps, and the scale at which its spirals are best framed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Misiurewicz {
+    pub c: Complex<f64>,
+    pub preperiod: u32,
+    pub period: u32,
+    pub scale: f64,
+}
+
+impl Misiurewicz {
+    pub fn find(c: &Complex<f64>, preperiod: u32, period: u32) -> Result<Misiurewicz, Error> {
+        let order = preperiod.checked_add(period).filter(|&n| n <= MAX_ORDER);
+        if preperiod == 0 || period == 0 || order.is_none() {
+            return Err(Error::InvalidArgument("preperiod and period must be positive and sum to at most 4096"));
+        }
+        let c = refine(c, preperiod, period).ok_or(Error::NotFound("Newton's method failed to converge"))?;
+        let (z, dz) = orbit(&c, preperiod + period);
+        let (k, p) = (preperiod as usize, period as usize);
+        let close = |a: Complex<f64>, b: Complex<f64>| (a - b).norm() < VERIFY_TOLERANCE * a.norm().max(1.0);
+        if (0..k).any(|i| close(z[i + p], z[i])) {
+            return Err(Error::NotFound("converged to a point of lower preperiod"));
+        }
+        if (1..p).any(|q| p % q == 0 && close(z[k + q], z[k])) {
+            return Err(Error::NotFound("converged to a point of lower period"));
+        }
+        // Features of the Julia set around the repelling cycle appear shrunk by dz/dc
+        let scale = 1.0 / dz[k].norm();
+        Ok(Misiurewicz { c, preperiod, period, scale })
+    }
+}
+
+// Iterates z_0..=z_n and their derivatives with respect to c
+fn orbit(c: &Complex<f64>, n: u32) -> (Vec<Complex<f64>>, Vec<Complex<f64>>) {
+    let mut z = vec![Complex::new(0.0, 0.0)];
+    let mut dz = vec![Complex::new(0.0, 0.0)];
+    for i in 0..n as usize {
+        dz.push(2.0 * z[i] * dz[i] + 1.0);
+        z.push(z[i] * z[i] + c);
+    }
+    (z, dz)
+}
+
+// Newton's method for (z_{k+p} - z_k) / prod_{i<k} (z_{i+p} - z_i) = 0, where the
+// denominator keeps the iteration away from points of lower preperiod
+fn refine(c: &Complex<f64>, preperiod: u32, period: u32) -> Option<Complex<f64>> {
+    let (k, p) = (preperiod as usize, period as usize);
+    let mut c = *c;
+    for _ in 0..NEWTON_STEPS {
+        let (z, dz) = orbit(&c, preperiod + period);
+        if z[k + p] == z[k] {
+            return Some(c);
+        }
+        let mut inverse_step = (dz[k + p] - dz[k]) / (z[k + p] - z[k]);
+        for i in 0..k {
+            inverse_step -= (dz[i + p] - dz[i]) / (z[i + p] - z[i]);
+        }
+        let delta = 1.0 / inverse_step;
+        c -= delta;
+        if !c.is_finite() {
+            return None;
+        }
+        if delta.norm() < NEWTON_TOLERANCE * c.norm().max(1.0) {
+            return Some(c);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The critical orbit of i is 0, i, -1 + i, -i, -1 + i, ...: preperiod 2 counting
+    // from z_0 = 0, and period 2
+    #[test]
+    fn converges_to_i() {
+        let point = Misiurewicz::find(&Complex::new(0.1, 0.9), 2, 2).unwrap();
+        assert!((point.c - Complex::new(0.0, 1.0)).norm() < 1e-12);
+        assert!((point.scale - 1.0 / 5f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_lower_preperiods() {
+        let lower = Err(Error::NotFound("converged to a point of lower preperiod"));
+        assert_eq!(Misiurewicz::find(&Complex::new(0.0, 1.0), 3, 2), lower);
+        assert_eq!(Misiurewicz::find(&Complex::new(0.0, 0.0), 1, 1), lower);
+    }
+}
@@ -0,0 +1,150 @@
+use num::Complex;
+
+use crate::colour::hsv;
+use crate::{ApplicationSettings, Error, PlotRange};
+
+const MAX_DEGREE: usize = 32;
+// Squared distance to a root at which an orbit counts as converged
+const TOLERANCE: f64 = 1e-12;
+// Brightness falloff per Newton step
+const SHADE: f64 = 0.08;
+const ROOT_ITERATIONS: u32 = 500;
+
+// Complex polynomial, coefficients in order of increasing degree
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Polynomial {
+    coefficients: Vec<Complex<f64>>,
+}
+
+impl Polynomial {
+    pub fn from_roots(roots: &[Complex<f64>]) -> Polynomial {
+        let mut coefficients = vec![Complex::new(1.0, 0.0)];
+        for root in roots {
+            let mut next = vec![Complex::new(0.0, 0.0); coefficients.len() + 1];
+            for (k, a) in coefficients.iter().enumerate() {
+                next[k + 1] += a;
+                next[k] -= a * root;
+            }
+            coefficients = next;
+        }
+        Polynomial { coefficients }
+    }
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+    // Horner evaluation of p(z) and p'(z)
+    pub fn evaluate(&self, z: &Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+        let mut p = Complex::new(0.0, 0.0);
+        let mut dp = Complex::new(0.0, 0.0);
+        for a in self.coefficients.iter().rev() {
+            dp = dp * z + p;
+            p = p * z + a;
+        }
+        (p, dp)
+    }
+    // Durand-Kerner iteration for all roots simultaneously
+    pub fn roots(&self) -> Vec<Complex<f64>> {
+        let lead = self.coefficients[self.degree()];
+        let monic = Polynomial { coefficients: self.coefficients.iter().map(|a| a / lead).collect() };
+        let seed = Complex::new(0.4, 0.9);
+        let mut roots: Vec<Complex<f64>> = (0..self.degree()).map(|k| seed.powu(k as u32)).collect();
+        for _ in 0..ROOT_ITERATIONS {
+            let mut change: f64 = 0.0;
+            for i in 0..roots.len() {
+                let denominator = roots.iter().enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .fold(Complex::new(1.0, 0.0), |acc, (_, r)| acc * (roots[i] - r));
+                let delta = monic.evaluate(&roots[i]).0 / denominator;
+                if delta.is_finite() {
+                    roots[i] -= delta;
+                    change = change.max(delta.norm_sqr());
+                }
+            }
+            if change < TOLERANCE * TOLERANCE {
+                break;
+            }
+        }
+        roots
+    }
+}
+
+pub(crate) struct Newton {
+    polynomial: Polynomial,
+    roots: Vec<Complex<f64>>,
+    // Whether the polynomial was given by its coefficients rather than its roots
+    by_coefficients: bool,
+}
+
+impl Default for Newton {
+    // z^3 - 1
+    fn default() -> Self {
+        let roots: Vec<Complex<f64>> = (0..3)
+            .map(|k| Complex::from_polar(1.0, 2.0 * std::f64::consts::PI * k as f64 / 3.0))
+            .collect();
+        Newton { polynomial: Polynomial::from_roots(&roots), roots, by_coefficients: false }
+    }
+}
+
+impl Newton {
+    // Roots and coefficients arrive from JS as interleaved real and imaginary parts
+    fn unpack(values: &[f64]) -> Result<Vec<Complex<f64>>, Error> {
+        if !values.len().is_multiple_of(2) {
+            return Err(Error::InvalidPolynomial("expected interleaved real and imaginary parts"));
+        }
+        if values.iter().any(|v| !v.is_finite()) {
+            return Err(Error::InvalidPolynomial("values must be finite"));
+        }
+        Ok(values.chunks(2).map(|pair| Complex::new(pair[0], pair[1])).collect())
+    }
+    pub fn from_roots(values: &[f64]) -> Result<Newton, Error> {
+        let roots = Newton::unpack(values)?;
+        if roots.is_empty() || roots.len() > MAX_DEGREE {
+            return Err(Error::InvalidPolynomial("degree must be between 1 and 32"));
+        }
+        Ok(Newton { polynomial: Polynomial::from_roots(&roots), roots, by_coefficients: false })
+    }
+    pub fn from_coefficients(values: &[f64]) -> Result<Newton, Error> {
+        let mut coefficients = Newton::unpack(values)?;
+        while coefficients.last().is_some_and(|a| a.norm_sqr() == 0.0) {
+            coefficients.pop();
+        }
+        if coefficients.len() < 2 || coefficients.len() > MAX_DEGREE + 1 {
+            return Err(Error::InvalidPolynomial("degree must be between 1 and 32"));
+        }
+        let polynomial = Polynomial { coefficients };
+        let roots = polynomial.roots();
+        Ok(Newton { polynomial, roots, by_coefficients: true })
+    }
+    // Whether the polynomial was given by its coefficients, and the values it was given
+    // by, interleaved as for from_roots and from_coefficients
+    pub fn definition(&self) -> (bool, Vec<f64>) {
+        let values = if self.by_coefficients { &self.polynomial.coefficients } else { &self.roots };
+        (self.by_coefficients, values.iter().flat_map(|z| vec![z.re, z.im]).collect())
+    }
+    // Index of the root the Newton iteration from z converges to, and the number of steps taken
+    pub fn converge(&self, z: &Complex<f64>, max_iterations: u32) -> Option<(usize, u32)> {
+        let mut z = *z;
+        for i in 0..max_iterations {
+            if let Some(root) = self.roots.iter().position(|r| (z - r).norm_sqr() < TOLERANCE) {
+                return Some((root, i));
+            }
+            let (p, dp) = self.polynomial.evaluate(&z);
+            z -= p / dp;
+            if !z.is_finite() {
+                return None;
+            }
+        }
+        None
+    }
+    pub fn render(&self, range: &PlotRange, settings: &ApplicationSettings,
+                  width: usize, height: usize, buffer: &mut [u32]) {
+        let roots = self.roots.len() as f64;
+        for (index, value) in buffer.iter_mut().enumerate() {
+            let z = range.pixel_to_point((index % width) as f64, (index / width) as f64, width, height);
+            *value = match self.converge(&z, settings.max_iterations) {
+                Some((root, steps)) => hsv(root as f64 / roots, 0.8, 1.0 / (1.0 + SHADE * steps as f64)),
+                None => 255 << 24
+            };
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use num::Complex;
+
+use crate::{ApplicationSettings, Error};
+
+// Half-width of the box searched for a period, relative to the view width
+pub(crate) const BOX_FRACTION: f64 = 1.0 / 32.0;
+const NEWTON_STEPS: u32 = 64;
+const NEWTON_TOLERANCE: f64 = 1e-15;
+
+// Center of a hyperbolic component together with the complex size estimate of its
+// minibrot, which maps the main cardioid's geometry onto the component's
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Nucleus {
+    pub c: Complex<f64>,
+    pub period: u32,
+    pub size: Complex<f64>,
+}
+
+impl Nucleus {
+    pub fn find(c: &Complex<f64>, radius: f64, settings: &ApplicationSettings) -> Result<Nucleus, Error> {
+        let period = box_period(c, radius, settings).ok_or(Error::NotFound("no periodic component near this point"))?;
+        Nucleus::of_period(c, period)
+    }
+    // Nucleus of the given period closest to c, e.g. the period of c's atom domain
+    pub fn of_period(c: &Complex<f64>, period: u32) -> Result<Nucleus, Error> {
+        let nucleus = refine(c, period).ok_or(Error::NotFound("Newton's method failed to converge"))?;
+        Ok(Nucleus { c: nucleus, period, size: size(&nucleus, period) })
+    }
+}
+
+// Box-period method: the first iteration at which the image of a square around c
+// surrounds the origin is the period of the lowest period nucleus inside the square
+fn box_period(c: &Complex<f64>, radius: f64, settings: &ApplicationSettings) -> Option<u32> {
+    let corners = [Complex::new(-radius, -radius), Complex::new(radius, -radius),
+                   Complex::new(radius, radius), Complex::new(-radius, radius)];
+    let corners: Vec<Complex<f64>> = corners.iter().map(|offset| c + offset).collect();
+    let mut z = vec![Complex::new(0.0, 0.0); corners.len()];
+    for n in 1..=settings.max_iterations {
+        for (z, c) in z.iter_mut().zip(corners.iter()) {
+            *z = *z * *z + c;
+        }
+        if z.iter().any(|z| z.norm_sqr() > settings.bailout * settings.bailout) {
+            return None;
+        }
+        if surrounds_origin(&z) {
+            return Some(n);
+        }
+    }
+    None
+}
+
+// Crossing number test of the origin against the closed polygon
+pub(crate) fn surrounds_origin(polygon: &[Complex<f64>]) -> bool {
+    let mut inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = &polygon[(i + 1) % polygon.len()];
+        if (a.im > 0.0) != (b.im > 0.0) && 0.0 < a.re + (b.re - a.re) * (0.0 - a.im) / (b.im - a.im) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// Newton's method for z_period(c) = 0
+fn refine(c: &Complex<f64>, period: u32) -> Option<Complex<f64>> {
+    let mut c = *c;
+    for _ in 0..NEWTON_STEPS {
+        let mut z = Complex::new(0.0, 0.0);
+        let mut dz = Complex::new(0.0, 0.0);
+        for _ in 0..period {
+            dz = 2.0 * z * dz + 1.0;
+            z = z * z + c;
+        }
+        let delta = z / dz;
+        c -= delta;
+        if !c.is_finite() {
+            return None;
+        }
+        if delta.norm() < NEWTON_TOLERANCE * c.norm().max(1.0) {
+            return Some(c);
+        }
+    }
+    None
+}
+
+// Size estimate of the minibrot with the given nucleus, relative to the whole set
+fn size(c: &Complex<f64>, period: u32) -> Complex<f64> {
+    let mut z = Complex::new(0.0, 0.0);
+    let mut l = Complex::new(1.0, 0.0);
+    let mut b = Complex::new(1.0, 0.0);
+    for _ in 1..period {
+        z = z * z + c;
+        l = 2.0 * z * l;
+        b += 1.0 / l;
+    }
+    1.0 / (b * l * l)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_period_finds_the_rabbit() {
+        let settings = ApplicationSettings::default();
+        let nucleus = Nucleus::find(&Complex::new(-0.1226, 0.7449), 0.01, &settings).unwrap();
+        assert_eq!(nucleus.period, 3);
+        assert!((nucleus.c - Complex::new(-0.122561, 0.744862)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn sizes_are_relative_to_the_main_cardioid() {
+        let main = Nucleus::of_period(&Complex::new(0.01, 0.0), 1).unwrap();
+        assert_eq!((main.c, main.size), (Complex::new(0.0, 0.0), Complex::new(1.0, 0.0)));
+        let rabbit = Nucleus::of_period(&Complex::new(-0.1226, 0.7449), 3).unwrap();
+        assert!(rabbit.size.norm() > 0.1 && rabbit.size.norm() < 1.0 / 3.0);
+    }
+}
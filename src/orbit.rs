@@ -0,0 +1,35 @@
+use num::Complex;
+
+use crate::{iterate, ApplicationSettings};
+
+// Longest cycle looked for at the end of a bounded orbit
+const MAX_PERIOD: usize = 1024;
+// Relative distance below which two iterates are considered to coincide
+const PERIOD_TOLERANCE: f64 = 1e-9;
+
+pub(crate) struct Orbit {
+    pub points: Vec<Complex<f64>>,
+    pub escape: Option<u32>,
+    pub period: Option<u32>,
+}
+
+impl Orbit {
+    pub fn new(c: &Complex<f64>, settings: &ApplicationSettings) -> Orbit {
+        let mut points = Vec::new();
+        let escape = iterate(c, settings, |z| points.push(*z)).map(|(i, _, _)| i);
+        let period = match escape {
+            Some(_) => None,
+            None => period(&points)
+        };
+        Orbit { points, escape, period }
+    }
+}
+
+// Smallest p such that the last iterate recurs p steps earlier
+pub(crate) fn period(orbit: &[Complex<f64>]) -> Option<u32> {
+    let last = orbit.last()?;
+    let tolerance = PERIOD_TOLERANCE * last.norm().max(1.0);
+    (1..=MAX_PERIOD.min(orbit.len() / 2))
+        .find(|&p| (last - orbit[orbit.len() - 1 - p]).norm_sqr() < tolerance * tolerance)
+        .map(|p| p as u32)
+}
@@ -0,0 +1,41 @@
+// Rasterizes a polyline given in (fractional) pixel coordinates into the buffer,
+// clipping every segment to the canvas first
+pub(crate) fn draw_polyline(buffer: &mut [u32], width: usize, height: usize,
+                            points: &[(f64, f64)], colour: u32) {
+    for segment in points.windows(2) {
+        if let Some((a, b)) = clip(segment[0], segment[1], width as f64, height as f64) {
+            let steps = (b.0 - a.0).abs().max((b.1 - a.1).abs()).ceil().max(1.0) as usize;
+            for step in 0..=steps {
+                let t = step as f64 / steps as f64;
+                let (x, y) = (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1));
+                if x >= 0.0 && y >= 0.0 && x < width as f64 && y < height as f64 {
+                    buffer[y as usize * width + x as usize] = colour;
+                }
+            }
+        }
+    }
+}
+
+// Liang-Barsky clipping of the segment a-b to [0, width] x [0, height]
+fn clip(a: (f64, f64), b: (f64, f64), width: f64, height: f64) -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1): (f64, f64) = (0.0, 1.0);
+    for (p, q) in [(-dx, a.0), (dx, width - a.0), (-dy, a.1), (dy, height - a.1)].iter() {
+        if *p == 0.0 {
+            if *q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = q / p;
+        if *p < 0.0 {
+            t0 = t0.max(t);
+        } else {
+            t1 = t1.min(t);
+        }
+        if t0 > t1 {
+            return None;
+        }
+    }
+    Some(((a.0 + t0 * dx, a.1 + t0 * dy), (a.0 + t1 * dx, a.1 + t1 * dy)))
+}
@@ -0,0 +1,134 @@
+use num::Complex;
+use std::fmt::Debug;
+use std::str::FromStr;
+
+use crate::lyapunov::Lyapunov;
+use crate::newton::Newton;
+use crate::{ApplicationSettings, Colouring, EscapeMetric, Error, Fractal, Lines, PlotRange};
+
+// Increased whenever the meaning of a parameter changes
+pub(crate) const VERSION: u32 = 1;
+
+// View state as keyword/value pairs for the text chunks of saved images
+pub(crate) fn describe(fractal: Fractal, range: &PlotRange, settings: &ApplicationSettings,
+                       formula: &str, newton: &Newton, lyapunov: &Lyapunov) -> Vec<(&'static str, String)> {
+    let center = range.center();
+    let (by_coefficients, values) = newton.definition();
+    let polynomial = values.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(" ");
+    vec![("Software", format!("mandelbrot-wasm {}", env!("CARGO_PKG_VERSION"))),
+         ("Parameter version", VERSION.to_string()),
+         ("Fractal", format!("{:?}", fractal)),
+         ("Center", format!("{} {}", center.re, center.im)),
+         ("Span", format!("{} {}", range.width(), -range.height())),
+         ("Iterations", settings.max_iterations.to_string()),
+         ("Iteration bounds", format!("{} {}", settings.min_iterations, settings.iteration_ceiling)),
+         ("Zoom", settings.zoom.to_string()),
+         ("Bailout", settings.bailout.to_string()),
+         ("Metric", format!("{:?}", settings.metric)),
+         ("Samples", format!("{} {}", settings.samples, settings.seed)),
+         ("Lines", format!("{:?} {}", settings.lines, settings.lines_only)),
+         ("Palette", format!("{:?} {}", settings.colouring, settings.sectors)),
+         ("Formula", formula.to_string()),
+         (if by_coefficients { "Newton coefficients" } else { "Newton roots" }, polynomial),
+         ("Lyapunov sequence", lyapunov.sequence())]
+}
+
+// View state recovered from the text chunks of a saved image
+pub(crate) struct Parameters {
+    pub fractal: Fractal,
+    pub range: PlotRange,
+    pub settings: ApplicationSettings,
+    pub formula: String,
+    pub newton: Newton,
+    pub lyapunov: Lyapunov,
+}
+
+const MALFORMED: Error = Error::InvalidImage("malformed view parameters");
+
+fn variant<T: Debug + Copy>(variants: &[T], name: &str) -> Result<T, Error> {
+    variants.iter().find(|variant| format!("{:?}", variant) == name).copied().ok_or(MALFORMED)
+}
+
+fn number<T: FromStr>(word: Option<&str>) -> Result<T, Error> {
+    word.and_then(|word| word.parse().ok()).ok_or(MALFORMED)
+}
+
+// Splits "first second" values
+fn pair(value: Option<&str>) -> Result<(&str, Option<&str>), Error> {
+    let mut words = value.ok_or(MALFORMED)?.splitn(2, ' ');
+    Ok((words.next().ok_or(MALFORMED)?, words.next()))
+}
+
+pub(crate) fn restore(text: &[(String, String)]) -> Result<Parameters, Error> {
+    let value = |keyword: &str| text.iter().find(|(key, _)| key == keyword).map(|(_, value)| value.as_str());
+    let version: u32 = number(value("Parameter version"))
+        .map_err(|_| Error::InvalidImage("no view parameters found"))?;
+    if version != VERSION {
+        return Err(Error::InvalidImage("unsupported parameter version"));
+    }
+    let fractal = variant(&[Fractal::Mandelbrot, Fractal::Buddhabrot, Fractal::Nebulabrot, Fractal::AntiBuddhabrot,
+                            Fractal::Newton, Fractal::Lyapunov, Fractal::Formula], value("Fractal").ok_or(MALFORMED)?)?;
+    let (re, im) = pair(value("Center"))?;
+    let center: Complex<f64> = Complex::new(number(Some(re))?, number(im)?);
+    let (width, height) = pair(value("Span"))?;
+    let half: Complex<f64> = Complex::new(number(Some(width))?, number(height)?) / 2.0;
+    let range = PlotRange { top_left: Complex::new(center.re - half.re, center.im + half.im),
+                            bottom_right: Complex::new(center.re + half.re, center.im - half.im) };
+    let bounds = pair(value("Iteration bounds"))?;
+    let samples = pair(value("Samples"))?;
+    let lines = pair(value("Lines"))?;
+    let palette = pair(value("Palette"))?;
+    let settings = ApplicationSettings {
+        zoom: number(value("Zoom"))?,
+        max_iterations: number(value("Iterations"))?,
+        min_iterations: number(Some(bounds.0))?,
+        iteration_ceiling: number(bounds.1)?,
+        bailout: number(value("Bailout"))?,
+        metric: variant(&[EscapeMetric::Euclidean, EscapeMetric::Manhattan, EscapeMetric::Maximum,
+                          EscapeMetric::Real, EscapeMetric::Imaginary], value("Metric").ok_or(MALFORMED)?)?,
+        samples: number(Some(samples.0))?,
+        seed: number(samples.1)?,
+        lines: variant(&[Lines::Off, Lines::Equipotentials, Lines::FieldLines, Lines::Both], lines.0)?,
+        lines_only: number(lines.1)?,
+        colouring: variant(&[Colouring::Iterations, Colouring::BinaryDecomposition, Colouring::Decomposition,
+                             Colouring::AngleHue, Colouring::AnglePalette, Colouring::AtomDomain], palette.0)?,
+        sectors: number(palette.1)?,
+    };
+    let formula = value("Formula").ok_or(MALFORMED)?.to_string();
+    let values = |text: &str| text.split_whitespace().map(|word| number(Some(word))).collect::<Result<Vec<f64>, Error>>();
+    let newton = match (value("Newton roots"), value("Newton coefficients")) {
+        (Some(roots), None) => Newton::from_roots(&values(roots)?)?,
+        (None, Some(coefficients)) => Newton::from_coefficients(&values(coefficients)?)?,
+        _ => return Err(MALFORMED)
+    };
+    let lyapunov = Lyapunov::new(value("Lyapunov sequence").ok_or(MALFORMED)?)?;
+    Ok(Parameters { fractal, range, settings, formula, newton, lyapunov })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_then_restore() {
+        let range = PlotRange { top_left: Complex::new(-1.5, 0.75), bottom_right: Complex::new(-0.5, 0.0) };
+        let settings = ApplicationSettings { zoom: 3.0, max_iterations: 1000, min_iterations: 32,
+                                             iteration_ceiling: 4096, bailout: 100.5,
+                                             metric: EscapeMetric::Manhattan, samples: 1234, seed: 99,
+                                             lines: Lines::FieldLines, lines_only: true,
+                                             colouring: Colouring::Decomposition, sectors: 7 };
+        let newton = Newton::from_roots(&[1.0, 0.0, -0.5, 0.25, -0.5, -0.25]).unwrap();
+        let lyapunov = Lyapunov::new("AABAB").unwrap();
+        let text: Vec<(String, String)> = describe(Fractal::Lyapunov, &range, &settings, "z^3 + c", &newton, &lyapunov)
+            .into_iter().map(|(key, value)| (key.to_string(), value)).collect();
+        let restored = restore(&text).unwrap();
+        assert_eq!(restored.fractal, Fractal::Lyapunov);
+        assert_eq!(restored.range, range);
+        assert_eq!(restored.settings, settings);
+        assert_eq!(restored.formula, "z^3 + c");
+        assert_eq!(restored.newton.definition(), newton.definition());
+        assert_eq!(restored.lyapunov.sequence(), "AABAB");
+        let missing: Vec<(String, String)> = text.into_iter().filter(|(key, _)| key != "Lyapunov sequence").collect();
+        assert!(restore(&missing).is_err());
+    }
+}
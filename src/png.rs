@@ -0,0 +1,489 @@
+use std::io::{self, Write};
+
+use crate::Error;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// Truecolour with alpha, 8 bits per channel
+const COLOUR_TYPE: u8 = 6;
+// Deflate window and the amount of input compressed into one block at a time
+const WINDOW: usize = 1 << 15;
+const BLOCK_SIZE: usize = 1 << 16;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 32;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59,
+                                67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4,
+                                5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385,
+                                  513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9,
+                                  10, 10, 11, 11, 12, 12, 13, 13];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = 0xffff_ffff;
+    for part in parts {
+        for &byte in part.iter() {
+            crc = CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+        }
+    }
+    crc ^ 0xffff_ffff
+}
+
+fn write_chunk<W: Write>(sink: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    sink.write_all(&(data.len() as u32).to_be_bytes())?;
+    sink.write_all(kind)?;
+    sink.write_all(data)?;
+    sink.write_all(&crc32(&[kind, data]).to_be_bytes())
+}
+
+// tEXt for plain ASCII, iTXt (UTF-8) for anything else
+fn text_chunk(keyword: &str, text: &str) -> (&'static [u8; 4], Vec<u8>) {
+    let mut data = keyword.as_bytes().to_vec();
+    data.push(0);
+    if text.is_ascii() {
+        data.extend_from_slice(text.as_bytes());
+        (b"tEXt", data)
+    } else {
+        // No compression, empty language tag and translated keyword
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(text.as_bytes());
+        (b"iTXt", data)
+    }
+}
+
+// LSB first bit stream, as deflate wants it
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl Bits {
+    fn write(&mut self, value: u32, length: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += length;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+    // Huffman codes are packed starting from their most significant bit
+    fn code(&mut self, code: u32, length: u32) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count);
+        }
+    }
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    let key = (data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32;
+    (key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+// Most recent position for each hash, linked to the previous one with the same hash
+struct Chains {
+    head: Vec<usize>,
+    previous: Vec<usize>,
+}
+
+impl Chains {
+    fn insert(&mut self, data: &[u8], i: usize) {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(data, i);
+            self.previous[i] = self.head[h];
+            self.head[h] = i;
+        }
+    }
+}
+
+// Streaming zlib compressor using the fixed Huffman codes of deflate
+struct Deflater {
+    // Up to WINDOW bytes of already compressed history, followed by pending input
+    data: Vec<u8>,
+    history: usize,
+    bits: Bits,
+    adler: (u32, u32),
+}
+
+impl Deflater {
+    fn new() -> Deflater {
+        let mut bits = Bits::default();
+        // 32K window, no preset dictionary, fastest compression
+        bits.write(0x78, 8);
+        bits.write(0x01, 8);
+        Deflater { data: Vec::new(), history: 0, bits, adler: (1, 0) }
+    }
+    fn write(&mut self, input: &[u8]) {
+        for &byte in input {
+            self.adler.0 = (self.adler.0 + byte as u32) % 65521;
+            self.adler.1 = (self.adler.1 + self.adler.0) % 65521;
+        }
+        self.data.extend_from_slice(input);
+    }
+    fn pending(&self) -> usize {
+        self.data.len() - self.history
+    }
+    fn literal(&mut self, value: u32) {
+        match value {
+            0..=143 => self.bits.code(0x30 + value, 8),
+            144..=255 => self.bits.code(0x190 + value - 144, 9),
+            256..=279 => self.bits.code(value - 256, 7),
+            _ => self.bits.code(0xc0 + value - 280, 8),
+        }
+    }
+    fn copy(&mut self, length: usize, distance: usize) {
+        let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap_or(0);
+        self.literal(257 + code as u32);
+        self.bits.write((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+        let code = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0);
+        self.bits.code(code as u32, 5);
+        self.bits.write((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+    }
+    // Compresses the pending input into one block, matching against hash chains
+    // which also cover the history
+    fn block(&mut self, last: bool) {
+        self.bits.write(last as u32, 1);
+        self.bits.write(1, 2);
+        let data = std::mem::take(&mut self.data);
+        let mut chains = Chains { head: vec![usize::MAX; 1 << HASH_BITS], previous: vec![usize::MAX; data.len()] };
+        for i in 0..self.history {
+            chains.insert(&data, i);
+        }
+        let mut i = self.history;
+        while i < data.len() {
+            let limit = MAX_MATCH.min(data.len() - i);
+            let (mut length, mut distance) = (0, 0);
+            if limit >= MIN_MATCH {
+                let mut candidate = chains.head[hash(&data, i)];
+                for _ in 0..MAX_CHAIN {
+                    if candidate == usize::MAX || i - candidate > WINDOW {
+                        break;
+                    }
+                    let matched = (0..limit).take_while(|&k| data[candidate + k] == data[i + k]).count();
+                    if matched > length {
+                        length = matched;
+                        distance = i - candidate;
+                        if matched == limit {
+                            break;
+                        }
+                    }
+                    candidate = chains.previous[candidate];
+                }
+            }
+            if length >= MIN_MATCH {
+                self.copy(length, distance);
+                for k in i..i + length {
+                    chains.insert(&data, k);
+                }
+                i += length;
+            } else {
+                self.literal(data[i] as u32);
+                chains.insert(&data, i);
+                i += 1;
+            }
+        }
+        self.literal(256);
+        let keep = data.len().min(WINDOW);
+        self.data = data[data.len() - keep..].to_vec();
+        self.history = keep;
+    }
+    // Compressed output produced so far
+    fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bits.bytes)
+    }
+    fn finish(&mut self) -> Vec<u8> {
+        self.block(true);
+        self.bits.align();
+        let adler = self.adler.1 << 16 | self.adler.0;
+        for byte in adler.to_be_bytes().iter() {
+            self.bits.write(*byte as u32, 8);
+        }
+        self.take()
+    }
+}
+
+// Writes an RGBA image row by row, so that only about one deflate block of
+// image data is held in memory at a time
+pub(crate) struct Encoder<W: Write> {
+    sink: W,
+    width: usize,
+    rows: usize,
+    deflater: Deflater,
+}
+
+impl<W: Write> Encoder<W> {
+    // `text` holds keyword/value pairs written ahead of the image data
+    pub fn new(mut sink: W, width: usize, height: usize, text: &[(&str, String)]) -> io::Result<Encoder<W>> {
+        sink.write_all(&SIGNATURE)?;
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        // Bit depth, colour type, compression, filter and interlace method
+        header.extend_from_slice(&[8, COLOUR_TYPE, 0, 0, 0]);
+        write_chunk(&mut sink, b"IHDR", &header)?;
+        for (keyword, value) in text {
+            let (kind, data) = text_chunk(keyword, value);
+            write_chunk(&mut sink, kind, &data)?;
+        }
+        Ok(Encoder { sink, width, rows: height, deflater: Deflater::new() })
+    }
+    // Pixels in the canvas' 0xAABBGGRR layout
+    pub fn write_row(&mut self, row: &[u32]) -> io::Result<()> {
+        if row.len() != self.width || self.rows == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "row does not fit the image"));
+        }
+        self.rows -= 1;
+        // Filter type None
+        self.deflater.write(&[0]);
+        for pixel in row {
+            self.deflater.write(&pixel.to_le_bytes());
+        }
+        if self.deflater.pending() >= BLOCK_SIZE {
+            self.deflater.block(false);
+            let compressed = self.deflater.take();
+            write_chunk(&mut self.sink, b"IDAT", &compressed)?;
+        }
+        Ok(())
+    }
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.rows > 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "image is missing rows"));
+        }
+        let compressed = self.deflater.finish();
+        write_chunk(&mut self.sink, b"IDAT", &compressed)?;
+        write_chunk(&mut self.sink, b"IEND", &[])?;
+        Ok(self.sink)
+    }
+}
+
+pub(crate) fn encode(buffer: &[u32], width: usize, height: usize, text: &[(&str, String)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let encoded = Encoder::new(&mut bytes, width, height, text).and_then(|mut encoder| {
+        for row in buffer.chunks(width).take(height) {
+            encoder.write_row(row)?;
+        }
+        encoder.finish()
+    });
+    // Writing into a Vec cannot fail and the rows fit by construction
+    debug_assert!(encoded.is_ok());
+    bytes
+}
+
+// Keyword/value pairs of the uncompressed text chunks of a PNG file, checking the
+// signature and the checksum of every chunk on the way
+pub(crate) fn read_text(bytes: &[u8]) -> Result<Vec<(String, String)>, Error> {
+    if bytes.get(..8) != Some(&SIGNATURE[..]) {
+        return Err(Error::InvalidImage("not a PNG file"));
+    }
+    let mut text = Vec::new();
+    let mut rest = &bytes[8..];
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+        // The specification limits chunk lengths to 2^31 - 1
+        if length > i32::MAX as u32 {
+            return Err(Error::InvalidImage("chunk too long"));
+        }
+        let length = length as usize;
+        if rest.len() - 12 < length {
+            break;
+        }
+        let (kind, data) = (&rest[4..8], &rest[8..8 + length]);
+        let crc = &rest[8 + length..12 + length];
+        if crc32(&[kind, data]).to_be_bytes() != crc {
+            return Err(Error::InvalidImage("corrupt chunk"));
+        }
+        let separator = data.iter().position(|&byte| byte == 0);
+        match (kind, separator) {
+            (b"IEND", _) => return Ok(text),
+            (b"tEXt", Some(end)) => {
+                // Latin-1 maps one to one onto the first 256 code points
+                let latin1 = |bytes: &[u8]| bytes.iter().map(|&byte| byte as char).collect::<String>();
+                text.push((latin1(&data[..end]), latin1(&data[end + 1..])));
+            }
+            (b"iTXt", Some(end)) if data.get(end + 1) == Some(&0) && data.len() >= end + 3 => {
+                // Skip the compression method, language tag and translated keyword
+                let fields = data[end + 3..].splitn(3, |&byte| byte == 0).collect::<Vec<&[u8]>>();
+                if let (Ok(keyword), Some(Ok(value))) = (std::str::from_utf8(&data[..end]),
+                                                         fields.get(2).map(|value| std::str::from_utf8(value))) {
+                    text.push((keyword.to_string(), value.to_string()));
+                }
+            }
+            _ => {}
+        }
+        rest = &rest[12 + length..];
+    }
+    Err(Error::InvalidImage("truncated PNG file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads the LSB first bit stream of deflate
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl<'a> Reader<'a> {
+        fn bit(&mut self) -> u32 {
+            let bit = (self.bytes[self.position / 8] >> (self.position % 8)) & 1;
+            self.position += 1;
+            bit as u32
+        }
+        fn bits(&mut self, length: u8) -> u32 {
+            (0..length).fold(0, |value, k| value | self.bit() << k)
+        }
+        // Huffman codes start from their most significant bit
+        fn code(&mut self, length: u8) -> u32 {
+            (0..length).fold(0, |value, _| value << 1 | self.bit())
+        }
+        fn symbol(&mut self) -> u32 {
+            let code = self.code(7);
+            if code < 0x18 {
+                return 256 + code;
+            }
+            let code = code << 1 | self.bit();
+            match code {
+                0x30..=0xbf => code - 0x30,
+                0xc0..=0xc7 => 280 + code - 0xc0,
+                _ => 144 + (code << 1 | self.bit()) - 0x190
+            }
+        }
+    }
+
+    // Decompresses a zlib stream of fixed Huffman blocks, checking its header and Adler-32
+    fn inflate(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(stream[0] & 0x0f, 8);
+        assert_eq!((stream[0] as u32 * 256 + stream[1] as u32) % 31, 0);
+        let mut reader = Reader { bytes: &stream[2..], position: 0 };
+        let mut output: Vec<u8> = Vec::new();
+        loop {
+            let last = reader.bits(1);
+            assert_eq!(reader.bits(2), 1, "not a fixed Huffman block");
+            loop {
+                let symbol = reader.symbol();
+                match symbol {
+                    0..=255 => output.push(symbol as u8),
+                    256 => break,
+                    _ => {
+                        let code = symbol as usize - 257;
+                        let length = LENGTH_BASE[code] as usize + reader.bits(LENGTH_EXTRA[code]) as usize;
+                        let code = reader.code(5) as usize;
+                        let distance = DISTANCE_BASE[code] as usize + reader.bits(DISTANCE_EXTRA[code]) as usize;
+                        for _ in 0..length {
+                            output.push(output[output.len() - distance]);
+                        }
+                    }
+                }
+            }
+            if last == 1 {
+                break;
+            }
+        }
+        let end = 2 + reader.position.div_ceil(8);
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in output.iter() {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(stream[end..], (b << 16 | a).to_be_bytes());
+        output
+    }
+
+    fn chunks(bytes: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let mut chunks = Vec::new();
+        let mut rest = &bytes[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let kind = [rest[4], rest[5], rest[6], rest[7]];
+            chunks.push((kind, &rest[8..8 + length]));
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn crc_matches_the_check_value() {
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn round_trip() {
+        let (width, height) = (211, 97);
+        // Runs which compress well alongside noise which does not, over several blocks
+        let mut state = 12345u32;
+        let buffer: Vec<u32> = (0..width * height).map(|index| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            if (index / width) % 3 == 0 { state } else { 0xff00_0000 | ((index % 7) as u32 * 0x10101) }
+        }).collect();
+        let text = [("Title", "Mandelbrot".to_string()), ("Comment", "z² + c".to_string())];
+        let bytes = encode(&buffer, width, height, &text);
+        assert_eq!(bytes[..8], SIGNATURE);
+
+        let chunks = chunks(&bytes);
+        assert_eq!(&chunks[0].0, b"IHDR");
+        assert_eq!(chunks[0].1[..8], [0, 0, 0, 211, 0, 0, 0, 97]);
+        assert_eq!(&chunks.last().unwrap().0, b"IEND");
+        let stream: Vec<u8> = chunks.iter().filter(|(kind, _)| kind == b"IDAT")
+            .flat_map(|(_, data)| data.iter().copied()).collect();
+        assert!(chunks.iter().filter(|(kind, _)| kind == b"IDAT").count() > 1);
+        let pixels = inflate(&stream);
+        assert_eq!(pixels.len(), height * (1 + 4 * width));
+        for (y, row) in pixels.chunks(1 + 4 * width).enumerate() {
+            assert_eq!(row[0], 0);
+            let decoded: Vec<u32> = row[1..].chunks(4)
+                .map(|rgba| u32::from_le_bytes([rgba[0], rgba[1], rgba[2], rgba[3]])).collect();
+            assert_eq!(decoded[..], buffer[y * width..(y + 1) * width]);
+        }
+
+        let expected: Vec<(String, String)> = text.iter().map(|(key, value)| (key.to_string(), value.clone())).collect();
+        assert_eq!(read_text(&bytes), Ok(expected));
+    }
+
+    #[test]
+    fn read_text_rejects_damage() {
+        let bytes = encode(&[0xff00_00ff; 4], 2, 2, &[("Title", "Mandelbrot".to_string())]);
+        let mut corrupt = bytes.clone();
+        // Inside the text of the tEXt chunk, which follows the 33 bytes up to IHDR's end
+        corrupt[33 + 8 + 2] ^= 1;
+        assert_eq!(read_text(&corrupt), Err(Error::InvalidImage("corrupt chunk")));
+        let mut oversized = bytes.clone();
+        oversized[33..37].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(read_text(&oversized), Err(Error::InvalidImage("chunk too long")));
+        assert_eq!(read_text(&bytes[..bytes.len() - 12]), Err(Error::InvalidImage("truncated PNG file")));
+        // iTXt chunks cut short after the compression flag or method are skipped
+        for short in [&b"Key\0\0"[..], &b"Key\0\0\0"[..]].iter() {
+            let mut chunk = Vec::new();
+            write_chunk(&mut chunk, b"iTXt", short).unwrap();
+            let spliced = [&bytes[..33], &chunk[..], &bytes[33..]].concat();
+            assert_eq!(read_text(&spliced), Ok(vec![("Title".to_string(), "Mandelbrot".to_string())]));
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+
+use crate::png::Encoder;
+use crate::PlotRange;
+
+// Pixels rendered at a time; together with the encoder's window this bounds the
+// memory of an export independently of the image size
+const STRIP_PIXELS: usize = 1 << 20;
+// Widest poster; every strip then holds at least two rows besides the overlap row
+pub(crate) const MAX_WIDTH: usize = STRIP_PIXELS / 2;
+
+// Renders a width x height image of the range in horizontal strips, each painted
+// into a small buffer by `paint`, and streams the rows to a PNG encoder.
+// `progress` receives the fraction of rows done after every strip.
+pub(crate) fn export<W, P, F>(sink: W, range: &PlotRange, width: usize, height: usize, text: &[(&str, String)],
+                              mut paint: P, mut progress: F) -> io::Result<W>
+    where W: Write, P: FnMut(&PlotRange, usize, usize, &mut [u32]), F: FnMut(f64) {
+    let rows = (STRIP_PIXELS / width.max(1)).max(1);
+    let mut encoder = Encoder::new(sink, width, height, text)?;
+    let mut buffer = Vec::new();
+    let mut y = 0;
+    while y < height {
+        let strip = rows.min(height - y);
+        // One extra row gives the line overlays the lower neighbours they compare against
+        let rendered = (strip + 1).min(height - y);
+        let strip_range = PlotRange { top_left: range.pixel_to_point(0.0, y as f64, width, height),
+                                      bottom_right: range.pixel_to_point(width as f64, (y + rendered) as f64,
+                                                                         width, height) };
+        buffer.clear();
+        buffer.resize(width * rendered, 0);
+        paint(&strip_range, width, rendered, &mut buffer);
+        for row in buffer.chunks(width).take(strip) {
+            encoder.write_row(row)?;
+        }
+        y += strip;
+        progress(y as f64 / height as f64);
+    }
+    encoder.finish()
+}
+
+// Hands the bytes written to a JS callback as Uint8Arrays
+pub(crate) struct JsSink<'a>(pub &'a js_sys::Function);
+
+impl Write for JsSink<'_> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.call1(&wasm_bindgen::JsValue::NULL, &js_sys::Uint8Array::from(bytes))
+            .map_err(|_| io::Error::other("sink callback threw"))?;
+        Ok(bytes.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use num::Complex;
+
+use crate::Error;
+
+// Points traced per level of dwell
+const SHARPNESS: u32 = 8;
+const ESCAPE_RADIUS: f64 = 65536.0;
+const NEWTON_STEPS: u32 = 16;
+const NEWTON_TOLERANCE: f64 = 1e-15;
+
+// Traces the external ray of angle numerator/denominator (in turns) inwards from
+// the escape radius by Newton's method on z_(k+1)(c) = target, where the target's
+// argument is the angle doubled k times. Doubling is done on the exact fraction,
+// so rational angles keep their full precision at any depth. Tracing stops after
+// max_points points, at dwell max_depth or once steps drop below min_step.
+pub(crate) fn external_ray(numerator: u32, denominator: u32, max_points: u32, max_depth: u32,
+                           min_step: f64) -> Result<Vec<Complex<f64>>, Error> {
+    if denominator == 0 || numerator >= denominator {
+        return Err(Error::InvalidArgument("angle must be a fraction in [0, 1)"));
+    }
+    let (mut numerator, denominator) = (numerator as u64, denominator as u64);
+    let turn = |numerator: u64| 2.0 * std::f64::consts::PI * numerator as f64 / denominator as f64;
+    let mut c = Complex::from_polar(ESCAPE_RADIUS, turn(numerator));
+    let mut ray = vec![c];
+    let (mut j, mut k) = (0, 0);
+    while (ray.len() as u32) < max_points && k < max_depth {
+        if j >= SHARPNESS {
+            numerator = 2 * numerator % denominator;
+            j = 0;
+            k += 1;
+        }
+        let radius = ESCAPE_RADIUS.powf(0.5f64.powf((j as f64 + 0.5) / SHARPNESS as f64));
+        let target = Complex::from_polar(radius, turn(numerator));
+        let previous = c;
+        for _ in 0..NEWTON_STEPS {
+            let mut z = Complex::new(0.0, 0.0);
+            let mut dz = Complex::new(0.0, 0.0);
+            for _ in 0..=k {
+                dz = 2.0 * z * dz + 1.0;
+                z = z * z + c;
+            }
+            let delta = (z - target) / dz;
+            c -= delta;
+            if delta.norm() < NEWTON_TOLERANCE * c.norm().max(1.0) {
+                break;
+            }
+        }
+        if !c.is_finite() {
+            break;
+        }
+        ray.push(c);
+        if (c - previous).norm() < min_step {
+            break;
+        }
+        j += 1;
+    }
+    Ok(ray)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The rays of angles 1/3 and 2/3 land at the parabolic root of the period 2 bulb,
+    // which they approach only slowly
+    #[test]
+    fn third_ray_heads_toward_the_period_2_root() {
+        let ray = external_ray(1, 3, 10_000, 64, 1e-9).unwrap();
+        let distance = |c: &Complex<f64>| (c - Complex::new(-0.75, 0.0)).norm();
+        let (middle, end) = (distance(&ray[ray.len() / 2]), distance(&ray[ray.len() - 1]));
+        assert!(end < 0.1 && end < middle / 2.0, "{} {}", middle, end);
+        assert!(ray.iter().all(|c| c.im > 0.0));
+    }
+}
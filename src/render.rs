@@ -0,0 +1,405 @@
+use num::Complex;
+use wasm_bindgen::prelude::*;
+
+use crate::{colour, escape_time, in_mandelbrot_set, lines, smooth_iteration, ApplicationSettings, Colouring,
+            EscapeMetric, Lines, PlotRange, DEGREE};
+
+// Rectangles narrower than this are iterated pixel by pixel
+const MIN_BLOCK: usize = 8;
+// Relative error allowed per arithmetic step when bounding orbits, generously above
+// machine epsilon so that the rounding of the per-pixel iteration stays covered
+const ROUNDING: f64 = 1e-14;
+// Slack on continuous iteration counts, which are computed with logarithms
+const TAU_MARGIN: f64 = 1e-9;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderStrategy {
+    BruteForce,
+    MarianiSilver,
+    BoundaryTrace
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Dwell {
+    // Short-circuited by the cardioid/bulb test
+    Interior,
+    // Still bounded after max_iterations
+    Bounded,
+    // Iteration at escape, the colour assigned to the pixel and its cell between the
+    // equipotentials and field lines, if drawn
+    Escaped(u32, u32, u32)
+}
+
+impl Dwell {
+    pub fn of(c: &Complex<f64>, settings: &ApplicationSettings) -> Dwell {
+        if in_mandelbrot_set(c) {
+            return Dwell::Interior;
+        }
+        match escape_time(c, settings) {
+            Some((tau, z)) => Dwell::escaped(tau, &z, settings),
+            None => Dwell::Bounded
+        }
+    }
+    pub fn escaped(tau: f64, z: &Complex<f64>, settings: &ApplicationSettings) -> Dwell {
+        let n = tau.floor() as u32;
+        Dwell::Escaped(n, colour::shade(n, z, settings), lines::cell(settings.lines, tau, z))
+    }
+    // Dwell shared by every point of the rectangle with corners `a` and `b` which the
+    // cardioid/bulb test leaves to iteration, if bounding the orbits of all of them by a
+    // disc shows that they escape in the same iteration band or all stay bounded. Only
+    // meaningful where `provable` holds. Also returns the iterations spent.
+    pub fn proven(a: &Complex<f64>, b: &Complex<f64>, settings: &ApplicationSettings) -> (Option<Dwell>, u64) {
+        let c = (a + b) / 2.0;
+        let rc = (b - a).norm() / 2.0 * (1.0 + ROUNDING) + ROUNDING * (a.norm() + b.norm());
+        let (mut z, mut r) = (Complex::new(0.0, 0.0), 0.0);
+        let (inner, outer) = (settings.bailout * (1.0 - ROUNDING), settings.bailout * (1.0 + ROUNDING));
+        for i in 0..settings.max_iterations {
+            let modulus = z.norm();
+            let size = modulus + r;
+            r = (2.0 * modulus + r) * r + rc + ROUNDING * (size * size + c.norm() + rc);
+            z = z * z + c;
+            let modulus = z.norm();
+            if modulus + r <= inner {
+                continue;
+            }
+            if modulus - r <= outer {
+                return (None, i as u64 + 1);
+            }
+            let tau = |modulus: f64| smooth_iteration(i, modulus, settings.bailout, DEGREE);
+            let (low, high) = ((tau(modulus + r) - TAU_MARGIN).max(0.0), tau(modulus - r) + TAU_MARGIN);
+            if low.floor() != high.floor() {
+                return (None, i as u64 + 1);
+            }
+            let n = low.floor() as u32;
+            return (Some(Dwell::Escaped(n, colour::shade(n, &z, settings), 0)), i as u64 + 1);
+        }
+        (Some(Dwell::Bounded), settings.max_iterations as u64)
+    }
+    pub fn colour(self) -> u32 {
+        match self {
+            Dwell::Escaped(_, colour, _) => colour,
+            _ => 255 << 24
+        }
+    }
+    // Iterations spent on a pixel with this dwell
+    pub fn iterations(self, max_iterations: u32) -> u64 {
+        match self {
+            Dwell::Interior => 0,
+            Dwell::Bounded => max_iterations as u64,
+            Dwell::Escaped(n, ..) => n as u64 + 1
+        }
+    }
+}
+
+pub(crate) struct Frame {
+    pub dwells: Vec<Dwell>,
+    // Number of pixels whose dwell was actually computed
+    pub iterated: usize,
+}
+
+// Statistics of the last update, only gathered for escape time images. Fractions refer
+// to all pixels of the frame and the dwell figures to the escaped ones, including pixels
+// filled in by the render strategy, which agree with brute force. The pixel and iteration
+// counts only cover the work actually done.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    // Milliseconds
+    pub wall_time: f64,
+    pub iterated_pixels: u32,
+    pub total_iterations: f64,
+    pub escaped: f64,
+    // Fraction of pixels which hit max_iterations
+    pub saturated: f64,
+    // Fraction of pixels decided by the cardioid/bulb test
+    pub short_circuited: f64,
+    pub min_dwell: u32,
+    pub max_dwell: u32,
+    pub mean_dwell: f64,
+}
+
+impl RenderStats {
+    // `iterations` is the total spent on the pixels which were actually iterated
+    pub(crate) fn of(frame: &Frame, iterations: u64) -> RenderStats {
+        let pixels = frame.dwells.len().max(1) as f64;
+        let (mut interior, mut bounded, mut escaped, mut sum) = (0, 0, 0, 0u64);
+        let (mut min_dwell, mut max_dwell) = (u32::MAX, 0);
+        for dwell in frame.dwells.iter() {
+            match *dwell {
+                Dwell::Interior => interior += 1,
+                Dwell::Bounded => bounded += 1,
+                Dwell::Escaped(n, ..) => {
+                    escaped += 1;
+                    sum += n as u64;
+                    min_dwell = min_dwell.min(n);
+                    max_dwell = max_dwell.max(n);
+                }
+            }
+        }
+        RenderStats { wall_time: 0.0,
+                      iterated_pixels: frame.iterated as u32,
+                      total_iterations: iterations as f64,
+                      escaped: escaped as f64 / pixels,
+                      saturated: bounded as f64 / pixels,
+                      short_circuited: interior as f64 / pixels,
+                      min_dwell: if escaped > 0 { min_dwell } else { 0 },
+                      max_dwell,
+                      mean_dwell: if escaped > 0 { sum as f64 / escaped as f64 } else { 0.0 } }
+    }
+}
+
+// Milliseconds since the epoch, for timing renders; std::time is unavailable on wasm32
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> f64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64() * 1000.0)
+}
+
+pub(crate) type DwellFn<'a> = &'a dyn Fn(&Complex<f64>) -> Dwell;
+// Dwell of all points of a rectangle given by two corners, if it can be shown to be shared
+pub(crate) type ProveFn<'a> = &'a dyn Fn(&Complex<f64>, &Complex<f64>) -> Option<Dwell>;
+
+// Whether `Dwell::proven` applies to the Mandelbrot set under these settings: the pixel
+// colour must only depend on the escape iteration, and the orbit bounds only refer to
+// the Euclidean escape test
+pub(crate) fn provable(settings: &ApplicationSettings) -> bool {
+    settings.metric == EscapeMetric::Euclidean && settings.colouring == Colouring::Iterations
+        && settings.lines == Lines::Off
+}
+
+// The subdividing strategies only skip pixels whose dwell `prove` vouches for, so they
+// produce the brute force image; without it they iterate every pixel anyway
+pub(crate) fn render(strategy: RenderStrategy, range: &PlotRange, dwell: DwellFn, prove: Option<ProveFn>,
+                     width: usize, height: usize) -> Frame {
+    match (strategy, prove) {
+        (RenderStrategy::MarianiSilver, Some(prove)) => mariani_silver(range, dwell, prove, width, height),
+        (RenderStrategy::BoundaryTrace, Some(prove)) => boundary_trace(range, dwell, prove, width, height),
+        _ => brute_force(range, dwell, width, height),
+    }
+}
+
+fn brute_force(range: &PlotRange, dwell: DwellFn, width: usize, height: usize) -> Frame {
+    let dwells = (0..width * height)
+        .map(|index| dwell(&range.pixel_to_point((index % width) as f64, (index / width) as f64, width, height)))
+        .collect();
+    Frame { dwells, iterated: width * height }
+}
+
+fn mariani_silver(range: &PlotRange, dwell: DwellFn, prove: ProveFn, width: usize, height: usize) -> Frame {
+    let mut grid = Grid::new(range, dwell, prove, width, height);
+    if width > 0 && height > 0 {
+        grid.subdivide(0, 0, width - 1, height - 1);
+    }
+    grid.into_frame()
+}
+
+fn boundary_trace(range: &PlotRange, dwell: DwellFn, prove: ProveFn, width: usize, height: usize) -> Frame {
+    let mut grid = Grid::new(range, dwell, prove, width, height);
+    grid.trace_boundaries();
+    grid.into_frame()
+}
+
+// Lazily evaluated dwell grid shared by the subdividing strategies
+pub(crate) struct Grid<'a> {
+    range: &'a PlotRange,
+    dwell: DwellFn<'a>,
+    prove: ProveFn<'a>,
+    width: usize,
+    height: usize,
+    dwells: Vec<Option<Dwell>>,
+    iterated: usize,
+}
+
+impl<'a> Grid<'a> {
+    pub fn new(range: &'a PlotRange, dwell: DwellFn<'a>, prove: ProveFn<'a>, width: usize,
+               height: usize) -> Grid<'a> {
+        Grid { range, dwell, prove, width, height, dwells: vec![None; width * height], iterated: 0 }
+    }
+    pub fn get(&mut self, x: usize, y: usize) -> Dwell {
+        let index = y * self.width + x;
+        match self.dwells[index] {
+            Some(dwell) => dwell,
+            None => {
+                let c = self.range.pixel_to_point(x as f64, y as f64, self.width, self.height);
+                let dwell = (self.dwell)(&c);
+                self.dwells[index] = Some(dwell);
+                self.iterated += 1;
+                dwell
+            }
+        }
+    }
+    pub fn into_frame(self) -> Frame {
+        let dwells = self.dwells.into_iter().map(|dwell| dwell.unwrap_or(Dwell::Bounded)).collect();
+        Frame { dwells, iterated: self.iterated }
+    }
+    fn point(&self, x: usize, y: usize) -> Complex<f64> {
+        self.range.pixel_to_point(x as f64, y as f64, self.width, self.height)
+    }
+    // Fills the pending pixels of the rectangle with inclusive bounds (x0, y0), (x1, y1)
+    // with the dwell `guess` of its surroundings if that is certain for all of them, and
+    // returns whether it was. Interior pixels pass the cardioid/bulb test, which is cheap
+    // enough to apply to each of them; any which fail are iterated. Other dwells need a
+    // proof covering the whole rectangle.
+    fn settle(&mut self, x0: usize, y0: usize, x1: usize, y1: usize, guess: Dwell) -> bool {
+        let proven = match guess {
+            Dwell::Interior => None,
+            _ => match (self.prove)(&self.point(x0, y0), &self.point(x1, y1)) {
+                Some(dwell) => Some(dwell),
+                None => return false
+            }
+        };
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let index = y * self.width + x;
+                if self.dwells[index].is_some() {
+                    continue;
+                }
+                if in_mandelbrot_set(&self.point(x, y)) {
+                    self.dwells[index] = Some(Dwell::Interior);
+                } else if proven.is_some() {
+                    self.dwells[index] = proven;
+                } else {
+                    self.get(x, y);
+                }
+            }
+        }
+        true
+    }
+    // Inclusive pixel bounds; the border of each rectangle is shared with its neighbours.
+    // A rectangle with uniform border dwell is most likely uniform inside, which `settle`
+    // makes sure of; rectangles enclosing the set within one escape band or filaments
+    // slipping between the border pixels fail it and are split further.
+    fn subdivide(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        if x1 - x0 < MIN_BLOCK || y1 - y0 < MIN_BLOCK {
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    self.get(x, y);
+                }
+            }
+            return;
+        }
+        let first = self.get(x0, y0);
+        let uniform = (x0..=x1).all(|x| self.get(x, y0) == first && self.get(x, y1) == first)
+            && (y0..=y1).all(|y| self.get(x0, y) == first && self.get(x1, y) == first);
+        if uniform && self.settle(x0 + 1, y0 + 1, x1 - 1, y1 - 1, first) {
+            return;
+        }
+        let (xm, ym) = ((x0 + x1) / 2, (y0 + y1) / 2);
+        self.subdivide(x0, y0, xm, ym);
+        self.subdivide(xm, y0, x1, ym);
+        self.subdivide(x0, ym, xm, y1);
+        self.subdivide(xm, ym, x1, y1);
+    }
+    // Fills the pending pixels x0..=x1 of row y like their left neighbour where `settle`
+    // allows, halving the run until it does or single pixels remain, which are iterated
+    fn fill_run(&mut self, x0: usize, x1: usize, y: usize) {
+        if x0 == x1 {
+            self.get(x0, y);
+            return;
+        }
+        let guess = self.get(x0 - 1, y);
+        if !self.settle(x0, y, x1, y, guess) {
+            let xm = (x0 + x1) / 2;
+            self.fill_run(x0, xm, y);
+            self.fill_run(xm + 1, x1, y);
+        }
+    }
+    // Follow the contours between pixels of differing dwell, starting from the image
+    // edges, then fill the regions they enclose from their left neighbours. Bands around
+    // the set and filaments between the contours are caught by `fill_run`.
+    fn trace_boundaries(&mut self) {
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 {
+            return;
+        }
+        let mut queued = vec![false; width * height];
+        let mut queue = Vec::new();
+        let mut enqueue = |index: usize, queue: &mut Vec<usize>| {
+            if !queued[index] {
+                queued[index] = true;
+                queue.push(index);
+            }
+        };
+        for y in 0..height {
+            enqueue(y * width, &mut queue);
+            enqueue(y * width + width - 1, &mut queue);
+        }
+        for x in 0..width {
+            enqueue(x, &mut queue);
+            enqueue((height - 1) * width + x, &mut queue);
+        }
+        while let Some(index) = queue.pop() {
+            let (x, y) = (index % width, index / width);
+            let center = self.get(x, y);
+            let (ll, rr, uu, dd) = (x > 0, x + 1 < width, y > 0, y + 1 < height);
+            let l = ll && self.get(x - 1, y) != center;
+            let r = rr && self.get(x + 1, y) != center;
+            let u = uu && self.get(x, y - 1) != center;
+            let d = dd && self.get(x, y + 1) != center;
+            if l { enqueue(index - 1, &mut queue); }
+            if r { enqueue(index + 1, &mut queue); }
+            if u { enqueue(index - width, &mut queue); }
+            if d { enqueue(index + width, &mut queue); }
+            if uu && ll && (l || u) { enqueue(index - width - 1, &mut queue); }
+            if uu && rr && (r || u) { enqueue(index - width + 1, &mut queue); }
+            if dd && ll && (l || d) { enqueue(index + width - 1, &mut queue); }
+            if dd && rr && (r || d) { enqueue(index + width + 1, &mut queue); }
+        }
+        for y in 1..height - 1 {
+            let mut x = 1;
+            while x < width - 1 {
+                let start = x;
+                while self.dwells[y * width + x].is_none() {
+                    x += 1;
+                }
+                if x > start {
+                    self.fill_run(start, x - 1, y);
+                }
+                x += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EscapeMetric, HEIGHT, START_RANGE, WIDTH};
+
+    // View of the given width centered on (re, im), with the aspect ratio of the canvas
+    fn view(re: f64, im: f64, width: f64) -> PlotRange {
+        let (dx, dy) = (width / 2.0, width * HEIGHT as f64 / WIDTH as f64 / 2.0);
+        PlotRange { top_left: Complex { re: re - dx, im: im + dy },
+                    bottom_right: Complex { re: re + dx, im: im - dy } }
+    }
+
+    // Half the canvas size keeps the test fast in debug builds
+    const TEST_WIDTH: usize = WIDTH / 2;
+    const TEST_HEIGHT: usize = HEIGHT / 2;
+
+    #[test]
+    fn strategies_match_brute_force() {
+        let settings = ApplicationSettings::default();
+        let real = ApplicationSettings { metric: EscapeMetric::Real, ..settings };
+        let views = [(START_RANGE, settings), (START_RANGE, real), (view(-0.745, 0.1, 0.02), settings),
+                     (view(-0.16, 1.035, 0.005), settings), (view(0.28, 0.01, 0.02), settings),
+                     (view(-1.768, 0.0, 0.01), settings)];
+        for (range, settings) in views.iter() {
+            let dwell = |c: &Complex<f64>| Dwell::of(c, settings);
+            let prove = |a: &Complex<f64>, b: &Complex<f64>| Dwell::proven(a, b, settings).0;
+            let prove = Some(&prove as ProveFn).filter(|_| provable(settings));
+            let expected = render(RenderStrategy::BruteForce, range, &dwell, None, TEST_WIDTH, TEST_HEIGHT);
+            for &strategy in [RenderStrategy::MarianiSilver, RenderStrategy::BoundaryTrace].iter() {
+                let frame = render(strategy, range, &dwell, prove, TEST_WIDTH, TEST_HEIGHT);
+                let differing = frame.dwells.iter().zip(expected.dwells.iter()).filter(|(a, b)| a != b).count();
+                assert_eq!(differing, 0, "{:?} at {:?}", strategy, range);
+            }
+        }
+    }
+}